@@ -10,6 +10,8 @@ pub mod lib;
 pub mod models;
 
 pub mod profile_orm;
+pub mod render_cache_orm;
+pub mod search_orm;
 pub mod tiny_url_orm;
 pub mod user_orm;
 
@@ -1,5 +1,6 @@
 pub mod challenges_model;
 pub mod posts_model;
 pub mod profiles_model;
+pub mod search_doc;
 pub mod users_model;
 pub mod tiny_url;
@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Clone, Deserialize, Debug)]
+pub struct SearchDoc {
+    pub path: String,
+    pub url: String,
+    pub title: String,
+    pub tags: Vec<String>,
+    pub mtime: i64,
+    #[serde(default)]
+    pub draft: bool,
+    #[serde(default)]
+    pub published_at: Option<i64>,
+    pub terms: BTreeMap<String, u32>,
+    #[serde(default)]
+    pub links: Vec<String>,
+    #[serde(default)]
+    pub version: u32,
+}
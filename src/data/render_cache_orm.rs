@@ -0,0 +1,72 @@
+use rocket::async_trait;
+use sled::Tree;
+
+use super::{
+    lib::{DbError, DbResult},
+    OrmInit,
+};
+
+// ? bump the suffix whenever rendered output changes, e.g. sanitization rules
+const RENDERED_TREE: &str = "rendered_html_v3";
+const KEY_SEPARATOR: char = '\u{0}';
+
+#[derive(Default)]
+pub struct RenderCacheOrm {}
+
+#[async_trait]
+impl OrmInit for RenderCacheOrm {
+    #[cfg(not(test))]
+    fn db_file_name(&self) -> String {
+        "render_cache.sled.db".to_owned()
+    }
+}
+
+fn db_error(e: impl std::fmt::Display) -> DbError {
+    DbError::internal_server_error(format!("render cache failed: {e}"))
+}
+
+fn open_tree(db: &sled::Db) -> DbResult<Tree> {
+    db.open_tree(RENDERED_TREE).map_err(db_error)
+}
+
+fn path_prefix(kind: &str, path: &str) -> String {
+    format!("{kind}{KEY_SEPARATOR}{path}{KEY_SEPARATOR}")
+}
+
+impl RenderCacheOrm {
+    pub async fn find_html(
+        &self,
+        db: &sled::Db,
+        kind: &str,
+        path: &str,
+        mtime: u128,
+    ) -> DbResult<Option<String>> {
+        let key = format!("{}{mtime}", path_prefix(kind, path));
+
+        Ok(open_tree(db)?
+            .get(key)
+            .map_err(db_error)?
+            .map(|raw| String::from_utf8_lossy(&raw).into_owned()))
+    }
+
+    pub async fn upsert_html(
+        &self,
+        db: &sled::Db,
+        kind: &str,
+        path: &str,
+        mtime: u128,
+        html: &str,
+    ) -> DbResult<()> {
+        let tree = open_tree(db)?;
+        let prefix = path_prefix(kind, path);
+
+        for key in tree.scan_prefix(&prefix).keys() {
+            tree.remove(key.map_err(db_error)?).map_err(db_error)?;
+        }
+
+        tree.insert(format!("{prefix}{mtime}"), html.as_bytes())
+            .map_err(db_error)?;
+
+        Ok(())
+    }
+}
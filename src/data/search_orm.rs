@@ -0,0 +1,115 @@
+use rocket::async_trait;
+use sled::Tree;
+use std::collections::HashSet;
+
+use super::models::search_doc::SearchDoc;
+use super::{
+    lib::{DbError, DbResult},
+    OrmInit,
+};
+
+const DOCS_TREE: &str = "search_docs";
+const TERMS_TREE: &str = "search_terms";
+const TERM_SEPARATOR: char = '\u{0}';
+
+#[derive(Default)]
+pub struct SearchOrm {}
+
+#[async_trait]
+impl OrmInit for SearchOrm {
+    #[cfg(not(test))]
+    fn db_file_name(&self) -> String {
+        "search.sled.db".to_owned()
+    }
+}
+
+fn db_error(e: impl std::fmt::Display) -> DbError {
+    DbError::internal_server_error(format!("search index failed: {e}"))
+}
+
+fn open_tree(db: &sled::Db, name: &str) -> DbResult<Tree> {
+    db.open_tree(name).map_err(db_error)
+}
+
+fn posting_key(term: &str, path: &str) -> String {
+    format!("{term}{TERM_SEPARATOR}{path}")
+}
+
+impl SearchOrm {
+    pub async fn find_doc(&self, db: &sled::Db, path: &str) -> DbResult<Option<SearchDoc>> {
+        match open_tree(db, DOCS_TREE)?.get(path).map_err(db_error)? {
+            Some(raw) => Ok(serde_json::from_slice(&raw).ok()),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn doc_paths(&self, db: &sled::Db) -> DbResult<Vec<String>> {
+        open_tree(db, DOCS_TREE)?
+            .iter()
+            .keys()
+            .map(|key| {
+                key.map(|key| String::from_utf8_lossy(&key).into_owned())
+                    .map_err(db_error)
+            })
+            .collect()
+    }
+
+    pub async fn find_docs(&self, db: &sled::Db, path_prefix: &str) -> DbResult<Vec<SearchDoc>> {
+        open_tree(db, DOCS_TREE)?
+            .scan_prefix(path_prefix)
+            .values()
+            .filter_map(|raw| match raw {
+                Ok(raw) => serde_json::from_slice(&raw).ok().map(Ok),
+                Err(e) => Some(Err(db_error(e))),
+            })
+            .collect()
+    }
+
+    pub async fn upsert_doc(&self, db: &sled::Db, doc: SearchDoc) -> DbResult<SearchDoc> {
+        self.remove_doc(db, &doc.path).await?;
+
+        let terms = open_tree(db, TERMS_TREE)?;
+        for term in doc.terms.keys() {
+            terms
+                .insert(posting_key(term, &doc.path), &[])
+                .map_err(db_error)?;
+        }
+
+        let json = serde_json::to_vec(&doc).map_err(db_error)?;
+        open_tree(db, DOCS_TREE)?
+            .insert(&doc.path, json)
+            .map_err(db_error)?;
+
+        Ok(doc)
+    }
+
+    pub async fn remove_doc(&self, db: &sled::Db, path: &str) -> DbResult<()> {
+        if let Some(doc) = self.find_doc(db, path).await? {
+            let terms = open_tree(db, TERMS_TREE)?;
+
+            for term in doc.terms.keys() {
+                terms.remove(posting_key(term, path)).map_err(db_error)?;
+            }
+
+            open_tree(db, DOCS_TREE)?.remove(path).map_err(db_error)?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn find_paths(&self, db: &sled::Db, term_prefix: &str) -> DbResult<HashSet<String>> {
+        open_tree(db, TERMS_TREE)?
+            .scan_prefix(term_prefix)
+            .keys()
+            .map(|key| {
+                let key = key.map_err(db_error)?;
+                let key = String::from_utf8_lossy(&key);
+
+                Ok(key
+                    .split_once(TERM_SEPARATOR)
+                    .map(|(_, path)| path.to_owned())
+                    .unwrap_or_default())
+            })
+            .collect()
+    }
+}
@@ -7,9 +7,11 @@ extern crate mustache;
 extern crate serde_derive;
 
 use log::{error, info, warn};
-use rocket::{fs::FileServer, launch, routes};
+use rocket::{fairing::AdHoc, fs::FileServer, launch, routes};
 
 use crate::utils::env::{from_env, EnvKey};
+use crate::utils::render_cache::{RenderCache, RENDER_CACHE_SIZE};
+use crate::utils::search::SearchIndex;
 
 mod data;
 mod routes;
@@ -33,22 +35,34 @@ async fn rocket() -> _ {
         warn!("{app_name} is running IN PRODUCTION");
     }
 
+    utils::previews::installed_frame_extractors();
+
     launch()
 }
 
 fn launch() -> rocket::Rocket<rocket::Build> {
     rocket::build()
         .manage(sled::open("hbp.sled.db").expect("hbp.sled.db doesn't exist...!"))
+        .manage(RenderCache::new(RENDER_CACHE_SIZE).persist(utils::env::is_prod()))
+        .manage(SearchIndex::default())
+        .manage(routes::files::FileIndex::default())
         .mount("/", utils::cors::options_routes())
         .mount("/", routes::index::index_routes())
         .mount("/ui", FileServer::from(from_env(EnvKey::SneuUiRoot)))
         .mount("/dev/null", routes::index::dev_null_routes())
         .mount("/markdown", routes::markdown::markdown_routes())
-        .mount("/static", routes![routes::static_files::serve])
+        .mount(
+            "/static",
+            routes![
+                routes::static_files::serve_highlight_css,
+                routes::static_files::serve
+            ],
+        )
         .mount("/users", routes::users::users_routes())
-        .mount("/blogs", routes![routes::blogs::index])
+        .mount("/blogs", routes::blogs::blogs_routes())
         .mount("/gallery", routes::nft_gallery::nfs_gallery_routes())
         .mount("/git", routes::git::git_routes())
+        .mount("/files", routes::files::files_routes())
         .mount("/tiny", routes::tiny_urls::tiny_urls_routes())
         // * API routes
         .mount("/api/v1/markdowns", routes::markdown::markdown_api_routes())
@@ -59,7 +73,20 @@ fn launch() -> rocket::Rocket<rocket::Build> {
         )
         .mount("/api/v1/profiles", routes::profiles::profiles_api_routes())
         .mount("/api/v1/files", routes::files::files_api_routes())
+        .mount("/api/v1/search", routes::markdown::search_api_routes())
         // * catchers
         .register("/", routes::catchers::catchers())
         .attach(utils::cors::Cors)
+        .attach(routes::files::file_index_invalidator())
+        .attach(AdHoc::on_liftoff("Search index", |rocket| {
+            Box::pin(async move {
+                if let (Some(db), Some(search_index)) =
+                    (rocket.state::<sled::Db>(), rocket.state::<SearchIndex>())
+                {
+                    if let Err(e) = search_index.refresh(db).await {
+                        error!("refresh() failed: {e:?}");
+                    }
+                }
+            })
+        }))
 }
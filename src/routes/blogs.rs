@@ -1,16 +1,39 @@
+use chrono::{DateTime, Utc};
 use httpstatus::StatusCode;
 use log::error;
-use rocket::get;
+use rocket::http::ContentType;
+use rocket::{get, routes, Route};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::path::Path;
 
 use crate::shared::entities::markdown::*;
 use crate::utils::auth::AuthPayload;
-use crate::utils::fso::{from_dir, render_fso_list};
-use crate::utils::responders::HbpResponse;
-use crate::utils::template::IndexLayout;
+use crate::utils::env::{from_env, EnvKey};
+use crate::utils::fso::{
+    dir_index_html, from_dir, is_draft, markdown_to_html, markdowns_in, render_fso_list,
+    render_fso_page, render_tag_list, sort_by_published, tag_counts, tagged_with,
+};
+use crate::utils::guards::headers::SiteUrl;
+use crate::utils::pagination::paginate;
+use crate::utils::responders::{HbpContent, HbpResponse, HbpResult};
+use crate::utils::template::{IndexLayout, Templater};
 
-#[get("/")]
-pub fn index(jwt: Option<AuthPayload>) -> HbpResponse {
-    let markdowns: Vec<FsoEntry> = match from_dir(&"markdown/blogs") {
+pub const BLOGS_ROOT: &str = "markdown/blogs";
+pub const BLOGS_TAGS_URL: &str = "/blogs/tags";
+const FEED_SIZE: usize = 20;
+
+fn can_see_drafts(jwt: &Option<AuthPayload>) -> bool {
+    jwt.as_ref().map(|jwt| jwt.is_root()).unwrap_or(false)
+}
+
+#[get("/?<page>&<per_page>")]
+pub fn index(
+    page: Option<usize>,
+    per_page: Option<usize>,
+    jwt: Option<AuthPayload>,
+) -> HbpResponse {
+    let mut markdowns: Vec<FsoEntry> = match from_dir(&BLOGS_ROOT) {
         Ok(markdowns) => markdowns,
         Err(e) => {
             error!("markdown_from_dir failed: {:?}", e);
@@ -19,10 +42,164 @@ pub fn index(jwt: Option<AuthPayload>) -> HbpResponse {
         }
     };
 
-    // FIXME: Now with dir, how to sort...?
+    if !can_see_drafts(&jwt) {
+        markdowns.retain(|markdown| !is_draft(markdown));
+    }
+
+    sort_by_published(&mut markdowns);
+    let (markdowns, pagination) = paginate(markdowns, page, per_page, "/blogs");
 
-    match render_fso_list(IndexLayout::from_title("Blogs").set_auth(jwt), markdowns) {
+    let index_html = match dir_index_html(Path::new(BLOGS_ROOT)) {
+        Ok(index_html) => index_html,
+        Err(e) => {
+            error!("dir_index_html failed: {:?}", e);
+            None
+        }
+    };
+
+    match render_fso_page(
+        IndexLayout::from_title("Blogs").set_auth(jwt),
+        markdowns,
+        Some(pagination),
+        index_html,
+    ) {
         Ok(html) => HbpResponse::html(html, StatusCode::Ok),
         Err(e) => HbpResponse::from_status(e.api_error.status_code),
     }
 }
+
+pub fn published_blogs(jwt: &Option<AuthPayload>) -> Vec<FsoMarkdown> {
+    let show_drafts = can_see_drafts(jwt);
+
+    markdowns_in(Path::new(BLOGS_ROOT))
+        .into_iter()
+        .filter(|markdown| show_drafts || !markdown.draft)
+        .collect()
+}
+
+#[get("/tags")]
+pub fn tags(jwt: Option<AuthPayload>) -> HbpResult<HbpResponse> {
+    let markdowns = published_blogs(&jwt);
+
+    let html = render_tag_list(
+        IndexLayout::from_title("Blog tags").set_auth(jwt),
+        tag_counts(&markdowns, BLOGS_TAGS_URL),
+    )?;
+
+    Ok(HbpResponse::html(html, StatusCode::Ok))
+}
+
+#[get("/tags/<tag>")]
+pub fn tagged(tag: &str, jwt: Option<AuthPayload>) -> HbpResult<HbpResponse> {
+    let mut markdowns: Vec<FsoEntry> = tagged_with(published_blogs(&jwt), tag)
+        .into_iter()
+        .map(FsoEntry::from)
+        .collect();
+    sort_by_published(&mut markdowns);
+
+    let html = render_fso_list(
+        IndexLayout::from_title(&format!("#{tag}")).set_auth(jwt),
+        markdowns,
+    )?;
+
+    Ok(HbpResponse::html(html, StatusCode::Ok))
+}
+
+#[derive(Serialize, Debug)]
+struct FeedDate {
+    rfc2822: String,
+    rfc3339: String,
+}
+impl From<DateTime<Utc>> for FeedDate {
+    fn from(date_time: DateTime<Utc>) -> Self {
+        Self {
+            rfc2822: date_time.to_rfc2822(),
+            rfc3339: date_time.to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct FeedItem {
+    title: String,
+    link: String,
+    author: Option<String>,
+    published: Option<FeedDate>,
+    tags: Vec<String>,
+    content_html: String,
+}
+
+#[derive(Serialize, Debug)]
+struct Feed {
+    title: String,
+    site_url: String,
+    feed_url: String,
+    updated: Option<FeedDate>,
+    items: Vec<FeedItem>,
+}
+
+fn render_feed(
+    template_path: &str,
+    feed_path: &str,
+    site_url: SiteUrl,
+    content_type: ContentType,
+) -> HbpResult<HbpResponse> {
+    let SiteUrl(site_url) = site_url;
+
+    let mut markdowns = published_blogs(&None);
+    markdowns.sort_by_key(|markdown| Reverse(markdown.published_at));
+    markdowns.truncate(FEED_SIZE);
+
+    let feed = Feed {
+        title: format!("{} | Blogs", from_env(EnvKey::AppName)),
+        feed_url: format!("{site_url}{feed_path}"),
+        updated: markdowns
+            .iter()
+            .filter_map(|markdown| markdown.published_at)
+            .max()
+            .map(FeedDate::from),
+        items: markdowns
+            .into_iter()
+            .map(|markdown| FeedItem {
+                link: format!("{site_url}/{}", markdown.url),
+                content_html: markdown_to_html(markdown.body()),
+                published: markdown.published_at.map(FeedDate::from),
+                author: Some(markdown.author).filter(|author| !author.is_empty()),
+                tags: markdown.tags.unwrap_or_default(),
+                title: markdown.title,
+            })
+            .collect(),
+        site_url,
+    };
+
+    let xml = Templater::new(template_path.into()).to_html(feed)?;
+
+    Ok(HbpResponse::ok(Some(HbpContent::Bytes(
+        xml.into_bytes(),
+        Box::new(Some(content_type)),
+    ))))
+}
+
+#[get("/feed.xml")]
+pub fn rss_feed(site_url: SiteUrl) -> HbpResult<HbpResponse> {
+    render_feed(
+        "blogs/rss.xml",
+        "/blogs/feed.xml",
+        site_url,
+        ContentType::new("application", "rss+xml"),
+    )
+}
+
+#[get("/atom.xml")]
+pub fn atom_feed(site_url: SiteUrl) -> HbpResult<HbpResponse> {
+    render_feed(
+        "blogs/atom.xml",
+        "/blogs/atom.xml",
+        site_url,
+        ContentType::new("application", "atom+xml"),
+    )
+}
+
+pub fn blogs_routes() -> Vec<Route> {
+    routes![index, tags, tagged, rss_feed, atom_feed]
+}
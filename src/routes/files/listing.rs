@@ -0,0 +1,193 @@
+use chrono::{DateTime, Utc};
+use rocket::form::FromFormField;
+use rocket::get;
+use std::cmp::Ordering;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::shared::{ApiList, DirectoryEntry, FileKind};
+use crate::utils::auth::AuthPayload;
+use crate::utils::env::trash_root;
+use crate::utils::has_preview;
+use crate::utils::pagination::{page_of, PageSize};
+use crate::utils::responders::{HbpApiResult, HbpJson, HbpResult};
+
+use super::{
+    assert_directory_access, attempt_access, matches_mime, mime_of, relative_path_of, resolve,
+    AccessMode,
+};
+
+const LISTING_PAGE_SIZE: PageSize = PageSize {
+    default: 100,
+    max: 1000,
+};
+
+#[derive(FromFormField, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SortBy {
+    #[default]
+    Name,
+    Size,
+    Modified,
+}
+
+#[derive(FromFormField, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+fn directory_entry_of(path: &Path) -> Option<DirectoryEntry> {
+    let metadata = fs::metadata(path).ok()?;
+    let kind = if metadata.is_dir() {
+        FileKind::Directory
+    } else {
+        FileKind::File
+    };
+    let mime = match kind {
+        FileKind::Directory => None,
+        FileKind::File => mime_guess::from_path(path)
+            .first()
+            .map(|mime| mime.to_string()),
+    };
+
+    Some(DirectoryEntry {
+        name: path.file_name()?.to_string_lossy().into_owned(),
+        path: relative_path_of(path),
+        kind,
+        size: match kind {
+            FileKind::Directory => 0,
+            FileKind::File => metadata.len(),
+        },
+        modified_at: metadata.modified().ok().map(DateTime::<Utc>::from),
+        mime,
+        has_preview: kind == FileKind::File && has_preview(path),
+    })
+}
+
+// * Readable children of `path`, the caller checks access to `path` itself
+pub(super) fn directory_entries_of(
+    path: &Path,
+    jwt: &Option<AuthPayload>,
+) -> HbpResult<Vec<DirectoryEntry>> {
+    Ok(fs::read_dir(path)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let entry_path = entry.path();
+
+            if entry_path == trash_root() {
+                return None;
+            }
+
+            // * Symlinks are listed where they point to, escaping ones are dropped
+            let resolved_path = resolve(relative_path_of(&entry_path)).ok()?;
+            attempt_access(&resolved_path, jwt, AccessMode::Read).ok()?;
+
+            let mut directory_entry = directory_entry_of(&resolved_path)?;
+            directory_entry.name = entry.file_name().to_string_lossy().into_owned();
+
+            Some(directory_entry)
+        })
+        .collect())
+}
+
+pub(super) fn sort_entries(entries: &mut [DirectoryEntry], sort: SortBy, order: SortOrder) {
+    entries.sort_by(|a, b| {
+        let ordering = match sort {
+            SortBy::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            SortBy::Size => a.size.cmp(&b.size),
+            SortBy::Modified => a.modified_at.cmp(&b.modified_at),
+        }
+        .then_with(|| a.name.cmp(&b.name));
+
+        let ordering = match order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        };
+
+        // * Directories always come first, whatever the order
+        match (a.kind, b.kind) {
+            (FileKind::Directory, FileKind::File) => Ordering::Less,
+            (FileKind::File, FileKind::Directory) => Ordering::Greater,
+            _ => ordering,
+        }
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+#[get("/dir/<path..>?<sort>&<order>&<mime>&<page>&<per_page>")]
+pub(super) async fn api_get_directory(
+    path: PathBuf,
+    sort: Option<SortBy>,
+    order: Option<SortOrder>,
+    mime: Option<String>,
+    page: Option<usize>,
+    per_page: Option<usize>,
+    jwt: Option<AuthPayload>,
+) -> HbpApiResult<DirectoryEntry> {
+    let path = resolve(path)?;
+    let mime = mime_of(mime)?;
+
+    attempt_access(&path, &jwt, AccessMode::Read)?;
+    assert_directory_access(&path)?;
+
+    let mut entries = directory_entries_of(&path, &jwt)?
+        .into_iter()
+        // ? The mime filter only applies to files, directories are kept for navigation
+        .filter(|directory_entry| match (&mime, directory_entry.kind) {
+            (Some(mime), FileKind::File) => matches_mime(Path::new(&directory_entry.name), mime),
+            _ => true,
+        })
+        .collect::<Vec<_>>();
+
+    sort_entries(
+        &mut entries,
+        sort.unwrap_or_default(),
+        order.unwrap_or_default(),
+    );
+
+    let (entries, page) = page_of(entries, page, per_page, LISTING_PAGE_SIZE);
+
+    Ok(HbpJson::List(ApiList::ok(entries).with_page(page)))
+}
+
+#[cfg(test)]
+mod listing_tests {
+    use super::{sort_entries, SortBy, SortOrder};
+    use crate::shared::{DirectoryEntry, FileKind};
+
+    fn entry_of(name: &str, kind: FileKind, size: u64) -> DirectoryEntry {
+        DirectoryEntry {
+            name: name.to_owned(),
+            path: format!("public/{name}"),
+            kind,
+            size,
+            modified_at: None,
+            mime: None,
+            has_preview: false,
+        }
+    }
+
+    fn names_of(entries: &[DirectoryEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    #[test]
+    fn directories_first() {
+        let mut entries = vec![
+            entry_of("b.jpg", FileKind::File, 3),
+            entry_of("Zoo", FileKind::Directory, 0),
+            entry_of("a.jpg", FileKind::File, 10),
+            entry_of("apps", FileKind::Directory, 0),
+        ];
+
+        sort_entries(&mut entries, SortBy::Name, SortOrder::Asc);
+        assert_eq!(names_of(&entries), ["apps", "Zoo", "a.jpg", "b.jpg"]);
+
+        sort_entries(&mut entries, SortBy::Size, SortOrder::Asc);
+        assert_eq!(names_of(&entries), ["Zoo", "apps", "b.jpg", "a.jpg"]);
+
+        sort_entries(&mut entries, SortBy::Name, SortOrder::Desc);
+        assert_eq!(names_of(&entries), ["Zoo", "apps", "b.jpg", "a.jpg"]);
+    }
+}
@@ -0,0 +1,433 @@
+use chrono::Utc;
+use httpstatus::StatusCode;
+use log::{error, warn};
+use rocket::{delete, get, post, State};
+use sled::Db;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::shared::interfaces::ApiError;
+use crate::shared::{ApiItem, ApiList, FileEntry, TrashedFile};
+use crate::utils::auth::AuthPayload;
+use crate::utils::env::{files_root, public_files_root, trash_root};
+use crate::utils::responders::{HbpApiResult, HbpJson, HbpResult};
+use crate::utils::search::SearchIndex;
+
+use super::{
+    assert_directory_access, assert_file_access, attempt_access, conflict_error, reindex,
+    relative_path_of, renamed_path_of, resolve, AccessMode, OnConflict, MAX_RENAME_ATTEMPTS,
+};
+
+fn destination_of(to: &str) -> HbpResult<PathBuf> {
+    let to = to.trim_start_matches('/');
+
+    if Path::new(to)
+        .components()
+        .all(|component| component == Component::CurDir)
+    {
+        return Err(ApiError::bad_request(vec!["destination is empty".to_owned()]).into());
+    }
+
+    resolve(to)
+}
+
+fn assert_not_root(path: &Path) -> HbpResult<()> {
+    if path == files_root() || path == public_files_root() {
+        Err(ApiError::forbidden()
+            .append_error(format!("{} can NOT be modified", relative_path_of(path)))
+            .into())
+    } else {
+        Ok(())
+    }
+}
+
+fn assert_not_nested(from: &Path, to: &Path) -> HbpResult<()> {
+    if to.starts_with(from) {
+        Err(ApiError::unprocessable_entity()
+            .append_error(format!(
+                "{} can NOT be moved or copied into itself",
+                relative_path_of(from)
+            ))
+            .into())
+    } else {
+        Ok(())
+    }
+}
+
+fn available_path_of(path: &Path, on_conflict: OnConflict) -> HbpResult<PathBuf> {
+    if fs::symlink_metadata(path).is_err() {
+        return Ok(path.to_owned());
+    }
+
+    match on_conflict {
+        OnConflict::Fail => Err(conflict_error(path).into()),
+        // * Overwritten files are recoverable from the trash
+        OnConflict::Overwrite => {
+            trash(path)?;
+            Ok(path.to_owned())
+        }
+        OnConflict::Rename => (1..MAX_RENAME_ATTEMPTS)
+            .map(|attempt| renamed_path_of(path, attempt))
+            .find(|candidate| fs::symlink_metadata(candidate).is_err())
+            .ok_or_else(|| conflict_error(path).into()),
+    }
+}
+
+fn create_parent_of(path: &Path) -> HbpResult<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    Ok(())
+}
+
+// ? Only `from` itself went through `resolve`, links below it may point anywhere, so they are skipped
+fn copy_recursively(from: &Path, to: &Path) -> HbpResult<()> {
+    let file_type = fs::symlink_metadata(from)?.file_type();
+
+    if file_type.is_dir() {
+        fs::create_dir(to)?;
+
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursively(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else if file_type.is_file() {
+        fs::copy(from, to)?;
+    } else {
+        warn!("copy_recursively() skipped {from:?}, it is NOT a regular file");
+    }
+
+    Ok(())
+}
+
+fn trash_metadata_path_of(id: &str) -> PathBuf {
+    trash_root().join(format!("{id}.json"))
+}
+
+fn trash(path: &Path) -> HbpResult<TrashedFile> {
+    fs::create_dir_all(trash_root())?;
+
+    let trashed_at = Utc::now();
+    let trashed_file = TrashedFile {
+        id: format!(
+            "{}-{:08x}",
+            trashed_at.timestamp_millis(),
+            rand::random::<u32>()
+        ),
+        path: relative_path_of(path),
+        is_dir: path.is_dir(),
+        trashed_at,
+    };
+    let trashed_path = trash_root().join(&trashed_file.id);
+
+    fs::rename(path, &trashed_path)?;
+
+    if let Err(e) = fs::write(
+        trash_metadata_path_of(&trashed_file.id),
+        serde_json::to_vec(&trashed_file)?,
+    ) {
+        error!("trash() failed to write metadata for {path:?}: {e}");
+        fs::rename(&trashed_path, path)?;
+
+        return Err(e.into());
+    }
+
+    Ok(trashed_file)
+}
+
+fn trashed_file_of(id: &str) -> HbpResult<TrashedFile> {
+    let is_valid_id = !id.is_empty()
+        && id
+            .chars()
+            .all(|char| char.is_ascii_hexdigit() || char == '-');
+
+    if !is_valid_id {
+        return Err(ApiError::not_found().into());
+    }
+
+    match fs::read(trash_metadata_path_of(id)) {
+        Ok(metadata) => Ok(serde_json::from_slice(&metadata)?),
+        Err(_) => Err(ApiError::not_found().into()),
+    }
+}
+
+fn trashed_files() -> HbpResult<Vec<TrashedFile>> {
+    let entries = match fs::read_dir(trash_root()) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(vec![]),
+    };
+
+    let mut trashed_files = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name();
+            let id = file_name.to_str()?.strip_suffix(".json")?;
+
+            trashed_file_of(id).ok()
+        })
+        .collect::<Vec<_>>();
+
+    trashed_files.sort_by_key(|trashed_file| std::cmp::Reverse(trashed_file.trashed_at));
+
+    Ok(trashed_files)
+}
+
+async fn delete(
+    path: PathBuf,
+    jwt: AuthPayload,
+    is_dir: bool,
+    db: &Db,
+    search_index: &SearchIndex,
+) -> HbpApiResult<TrashedFile> {
+    let path = resolve(path)?;
+
+    attempt_access(&path, &Some(jwt), AccessMode::Write)?;
+    assert_not_root(&path)?;
+
+    if is_dir {
+        assert_directory_access(&path)?;
+    } else {
+        assert_file_access(&path)?;
+    }
+
+    let trashed_file = trash(&path)?;
+    reindex(db, search_index, &[path]).await;
+
+    Ok(HbpJson::Item(ApiItem::ok(trashed_file)))
+}
+
+#[post("/mkdir/<path..>")]
+pub(super) fn api_make_directory(path: PathBuf, jwt: AuthPayload) -> HbpApiResult<FileEntry> {
+    let path = resolve(path)?;
+
+    attempt_access(&path, &Some(jwt), AccessMode::Write)?;
+
+    if fs::symlink_metadata(&path).is_ok() {
+        return Err(conflict_error(&path).into());
+    }
+
+    fs::create_dir_all(&path)?;
+
+    Ok(HbpJson::Item(ApiItem::created(FileEntry {
+        path: relative_path_of(&path),
+    })))
+}
+
+#[post("/move/<path..>?<to>&<on_conflict>")]
+pub(super) async fn api_move(
+    path: PathBuf,
+    to: &str,
+    on_conflict: Option<OnConflict>,
+    jwt: AuthPayload,
+    db: &State<Db>,
+    search_index: &State<SearchIndex>,
+) -> HbpApiResult<FileEntry> {
+    let jwt = Some(jwt);
+    let from = resolve(path)?;
+    let to = destination_of(to)?;
+
+    attempt_access(&from, &jwt, AccessMode::Write)?;
+    attempt_access(&to, &jwt, AccessMode::Write)?;
+    assert_not_root(&from)?;
+    assert_not_root(&to)?;
+
+    if fs::symlink_metadata(&from).is_err() {
+        return Err(ApiError::not_found().into());
+    }
+
+    assert_not_nested(&from, &to)?;
+
+    let to = available_path_of(&to, on_conflict.unwrap_or_default())?;
+    create_parent_of(&to)?;
+    fs::rename(&from, &to)?;
+    reindex(db, search_index, &[from, to.clone()]).await;
+
+    Ok(HbpJson::Item(ApiItem::ok(FileEntry {
+        path: relative_path_of(&to),
+    })))
+}
+
+#[post("/copy/<path..>?<to>&<on_conflict>")]
+pub(super) async fn api_copy(
+    path: PathBuf,
+    to: &str,
+    on_conflict: Option<OnConflict>,
+    jwt: AuthPayload,
+    db: &State<Db>,
+    search_index: &State<SearchIndex>,
+) -> HbpApiResult<FileEntry> {
+    let jwt = Some(jwt);
+    let from = resolve(path)?;
+    let to = destination_of(to)?;
+
+    attempt_access(&from, &jwt, AccessMode::Read)?;
+    attempt_access(&to, &jwt, AccessMode::Write)?;
+    assert_not_root(&to)?;
+
+    if !from.exists() {
+        return Err(ApiError::not_found().into());
+    }
+
+    assert_not_nested(&from, &to)?;
+
+    let to = available_path_of(&to, on_conflict.unwrap_or_default())?;
+    create_parent_of(&to)?;
+
+    if let Err(e) = copy_recursively(&from, &to) {
+        // * Do NOT leave a half-copied directory behind
+        if to.is_dir() {
+            fs::remove_dir_all(&to)?;
+        } else if to.exists() {
+            fs::remove_file(&to)?;
+        }
+
+        return Err(e);
+    }
+
+    reindex(db, search_index, std::slice::from_ref(&to)).await;
+
+    Ok(HbpJson::Item(ApiItem::created(FileEntry {
+        path: relative_path_of(&to),
+    })))
+}
+
+#[delete("/raw/<path..>")]
+pub(super) async fn api_delete_file(
+    path: PathBuf,
+    jwt: AuthPayload,
+    db: &State<Db>,
+    search_index: &State<SearchIndex>,
+) -> HbpApiResult<TrashedFile> {
+    delete(path, jwt, false, db, search_index).await
+}
+
+#[delete("/dir/<path..>")]
+pub(super) async fn api_delete_directory(
+    path: PathBuf,
+    jwt: AuthPayload,
+    db: &State<Db>,
+    search_index: &State<SearchIndex>,
+) -> HbpApiResult<TrashedFile> {
+    delete(path, jwt, true, db, search_index).await
+}
+
+#[get("/trash")]
+pub(super) fn api_get_trash(jwt: AuthPayload) -> HbpApiResult<TrashedFile> {
+    let jwt = Some(jwt);
+    let trashed_files = trashed_files()?
+        .into_iter()
+        .filter(|trashed_file| {
+            resolve(&trashed_file.path)
+                .and_then(|path| attempt_access(&path, &jwt, AccessMode::Write))
+                .is_ok()
+        })
+        .collect();
+
+    Ok(HbpJson::List(ApiList::ok(trashed_files)))
+}
+
+#[post("/trash/<id>/restore?<on_conflict>")]
+pub(super) async fn api_restore_trashed(
+    id: &str,
+    on_conflict: Option<OnConflict>,
+    jwt: AuthPayload,
+    db: &State<Db>,
+    search_index: &State<SearchIndex>,
+) -> HbpApiResult<FileEntry> {
+    let trashed_file = trashed_file_of(id)?;
+    let path = resolve(&trashed_file.path)?;
+
+    attempt_access(&path, &Some(jwt), AccessMode::Write)?;
+
+    let path = available_path_of(&path, on_conflict.unwrap_or_default())?;
+    create_parent_of(&path)?;
+    fs::rename(trash_root().join(&trashed_file.id), &path)?;
+    fs::remove_file(trash_metadata_path_of(&trashed_file.id))?;
+    reindex(db, search_index, std::slice::from_ref(&path)).await;
+
+    Ok(HbpJson::Item(ApiItem::ok(FileEntry {
+        path: relative_path_of(&path),
+    })))
+}
+
+#[delete("/trash/<id>")]
+pub(super) async fn api_purge_trashed(
+    id: &str,
+    jwt: AuthPayload,
+    db: &State<Db>,
+    search_index: &State<SearchIndex>,
+) -> HbpApiResult<TrashedFile> {
+    let trashed_file = trashed_file_of(id)?;
+    let path = resolve(&trashed_file.path)?;
+
+    attempt_access(&path, &Some(jwt), AccessMode::Write)?;
+
+    let trashed_path = trash_root().join(&trashed_file.id);
+
+    if trashed_path.is_dir() {
+        fs::remove_dir_all(&trashed_path)?;
+    } else if fs::symlink_metadata(&trashed_path).is_ok() {
+        fs::remove_file(&trashed_path)?;
+    }
+
+    fs::remove_file(trash_metadata_path_of(&trashed_file.id)).map_err(|e| {
+        error!("api_purge_trashed() failed for {}: {e}", trashed_file.id);
+        ApiError::from_message(&e.to_string(), StatusCode::InternalServerError)
+    })?;
+    reindex(db, search_index, &[path]).await;
+
+    Ok(HbpJson::Item(ApiItem::ok(trashed_file)))
+}
+
+#[cfg(test)]
+mod manage_tests {
+    use super::{copy_recursively, destination_of};
+    use std::fs;
+
+    #[test]
+    fn reject_hostile_destinations() {
+        assert!(destination_of("public/../../etc/passwd").is_err());
+        assert!(destination_of("").is_err());
+        assert!(destination_of("./").is_err());
+    }
+
+    #[test]
+    fn copy_directories_recursively() {
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("from");
+        fs::create_dir_all(from.join("nested")).unwrap();
+        fs::write(from.join("a.txt"), "a").unwrap();
+        fs::write(from.join("nested/b.txt"), "b").unwrap();
+
+        let to = dir.path().join("to");
+        copy_recursively(&from, &to).unwrap();
+
+        assert_eq!(fs::read_to_string(to.join("a.txt")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(to.join("nested/b.txt")).unwrap(), "b");
+        assert!(from.join("nested/b.txt").exists());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn skip_symlinks_when_copying() {
+        let outside = tempfile::tempdir().unwrap();
+        fs::create_dir(outside.path().join("secrets")).unwrap();
+        fs::write(outside.path().join("secrets/key.txt"), "key").unwrap();
+        fs::write(outside.path().join("passwd"), "root").unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("from");
+        fs::create_dir_all(&from).unwrap();
+        fs::write(from.join("a.txt"), "a").unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secrets"), from.join("secrets")).unwrap();
+        std::os::unix::fs::symlink(outside.path().join("passwd"), from.join("passwd")).unwrap();
+
+        let to = dir.path().join("to");
+        copy_recursively(&from, &to).unwrap();
+
+        assert_eq!(fs::read_to_string(to.join("a.txt")).unwrap(), "a");
+        assert!(fs::symlink_metadata(to.join("secrets")).is_err());
+        assert!(fs::symlink_metadata(to.join("passwd")).is_err());
+    }
+}
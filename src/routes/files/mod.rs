@@ -1,26 +1,58 @@
-use async_recursion::async_recursion;
-use async_std::fs::read_dir;
-use async_std::path::PathBuf as AsyncPathBuf;
-
-use crate::shared::{ApiItem, Directory};
-use futures::StreamExt;
+use httpstatus::StatusCode;
 use log::error;
 use mime_guess::Mime;
-use rand::{seq::SliceRandom, thread_rng};
-use rocket::{get, routes, uri, Route};
+use rocket::form::FromFormField;
+use rocket::{get, routes, Route};
+use sled::Db;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use crate::shared::interfaces::ApiError;
-use crate::utils::create_thumbnail;
-use crate::utils::responders::{HbpApiResult, HbpJson, HbpResult};
+use crate::utils::downloads::{content_disposition_of, is_active_content, ByteRange, Disposition};
+use crate::utils::guards::headers::{CacheValidators, RangeHeaders};
+use crate::utils::paths::{relative_path_in, resolve_in};
+use crate::utils::render_cache::RenderStamp;
+use crate::utils::responders::HbpResult;
+use crate::utils::search::SearchIndex;
 use crate::utils::{
     auth::AuthPayload,
-    env::{files_root, public_files_root},
+    env::{files_root, public_files_root, trash_root},
     responders::HbpResponse,
 };
 
-fn attempt_access(path: &Path, jwt: &Option<AuthPayload>) -> HbpResult<()> {
+mod listing;
+mod manage;
+mod previews;
+mod random;
+mod ui;
+mod upload;
+
+use listing::*;
+use manage::*;
+use previews::*;
+use random::*;
+use ui::*;
+use upload::*;
+
+pub use random::{file_index_invalidator, FileIndex};
+
+const MAX_RENAME_ATTEMPTS: usize = 1000;
+
+#[derive(FromFormField, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OnConflict {
+    #[default]
+    Fail,
+    Overwrite,
+    Rename,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AccessMode {
+    Read,
+    Write,
+}
+
+fn attempt_access(path: &Path, jwt: &Option<AuthPayload>, mode: AccessMode) -> HbpResult<()> {
     fn is_private(path: &Path) -> bool {
         let is_in_public_folder = path.starts_with(public_files_root());
 
@@ -31,6 +63,22 @@ fn attempt_access(path: &Path, jwt: &Option<AuthPayload>) -> HbpResult<()> {
         true
     }
 
+    // * The trash is only reachable through the trash endpoints
+    if path.starts_with(trash_root()) {
+        return Err(ApiError::forbidden().into());
+    }
+
+    if mode == AccessMode::Write {
+        return match jwt {
+            // * Signed resource links are read-only
+            Some(AuthPayload::UserResource(_)) => Err(ApiError::forbidden().into()),
+            // * Any user can write into the public folder, only root elsewhere
+            Some(jwt) if is_private(path) => jwt.match_path(path, |_, _| jwt.is_root()),
+            Some(_) => Ok(()),
+            None => Err(ApiError::unauthorized().into()),
+        };
+    }
+
     if is_private(path) {
         match jwt {
             Some(jwt) => {
@@ -47,6 +95,63 @@ fn attempt_access(path: &Path, jwt: &Option<AuthPayload>) -> HbpResult<()> {
     }
 }
 
+fn resolve(path: impl AsRef<Path>) -> HbpResult<PathBuf> {
+    resolve_in(&files_root(), path.as_ref())
+}
+
+fn relative_path_of(path: &Path) -> String {
+    relative_path_in(&files_root(), path).unwrap_or_else(|| path.to_string_lossy().into_owned())
+}
+
+fn renamed_path_of(path: &Path, attempt: usize) -> PathBuf {
+    let file_stem = path
+        .file_stem()
+        .map(|file_stem| file_stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    path.with_file_name(format!("{file_stem} ({attempt}){extension}"))
+}
+
+fn conflict_error(path: &Path) -> ApiError {
+    ApiError::from_message(
+        &format!("{} already exists", relative_path_of(path)),
+        StatusCode::Conflict,
+    )
+}
+
+// * Notes written through the files API show up in search & wiki links right away
+async fn reindex(db: &Db, search_index: &SearchIndex, written_paths: &[PathBuf]) {
+    if let Err(e) = search_index.reindex(db, written_paths).await {
+        error!("reindex() failed for {written_paths:?}: {e:?}");
+    }
+}
+
+fn mime_of(mime: Option<String>) -> HbpResult<Option<Mime>> {
+    mime.map(|mime| {
+        Mime::from_str(&mime).map_err(|e| {
+            error!("{e:?}");
+            ApiError::bad_request(vec!["file_type is malformed".to_owned()]).into()
+        })
+    })
+    .transpose()
+}
+
+// * `image/*` or `*/*` style wildcards match any (sub) type
+fn matches_mime(path: &Path, mime: &Mime) -> bool {
+    mime_guess::from_path(path).iter().any(|item| {
+        let (sup_type, sub_type) = (mime.type_().as_str(), mime.subtype().as_str());
+
+        let match_sup_type = sup_type.is_empty() || sup_type == "*" || item.type_() == sup_type;
+        let match_sub_type = sub_type.is_empty() || sub_type == "*" || item.subtype() == sub_type;
+
+        match_sup_type && match_sub_type
+    })
+}
+
 fn assert_file_access(path: &Path) -> HbpResult<&Path> {
     if path.is_dir() {
         Err(ApiError::unprocessable_entity()
@@ -73,163 +178,141 @@ fn assert_directory_access(path: &Path) -> HbpResult<&Path> {
     }
 }
 
-#[get("/dir/<path..>")]
-async fn api_get_directory(path: PathBuf, jwt: Option<AuthPayload>) -> HbpApiResult<Directory> {
-    let path = files_root().join(path);
-
-    attempt_access(&path, &jwt)?;
-    assert_directory_access(&path)?;
-
-    let item = Directory {
-        children: read_dir(path)
-            .await?
-            .map(|entry| {
-                entry.unwrap().path().to_string_lossy()[files_root().to_string_lossy().len() + 1..]
-                    .to_owned()
-            })
-            .collect()
-            .await,
-    };
-
-    Ok(HbpJson::Item(ApiItem::ok(item)))
-}
-
-#[get("/random/raw?<mime>&<preview>")]
-async fn api_get_random_file(
-    mime: Option<String>,
+#[get("/raw/<path..>?<disposition>&<filename>", rank = 2)]
+async fn api_get_raw_file(
     jwt: Option<AuthPayload>,
-    preview: Option<bool>,
+    path: PathBuf,
+    disposition: Option<Disposition>,
+    filename: Option<String>,
+    validators: CacheValidators,
+    range_headers: RangeHeaders,
 ) -> HbpResult<HbpResponse> {
-    let mime = if let Some(mime) = mime {
-        let mime = Mime::from_str(&mime).map_err(|e| {
-            error!("{e:?}");
-            ApiError::bad_request(vec!["file_type is malformed".to_owned()])
-        })?;
-
-        Some(mime)
-    } else {
-        None
-    };
-
-    #[async_recursion]
-    async fn get_matched_files(
-        path: &Path,
-        jwt: &Option<AuthPayload>,
-        mime: &Option<Mime>,
-    ) -> HbpResult<Vec<AsyncPathBuf>> {
-        let mut matched_files = vec![];
-
-        match read_dir(path).await {
-            Ok(mut entries) => {
-                while let Some(entry) = entries.next().await {
-                    let entry = entry?;
-                    let meta_data = entry.metadata().await?;
-
-                    let can_access = attempt_access(entry.path().as_path().into(), jwt)
-                        .map(|()| true)
-                        .unwrap_or(false);
-
-                    if !can_access {
-                        continue;
-                    }
-
-                    if meta_data.is_file() {
-                        let match_file_type =
-                            mime_guess::from_path(entry.path()).iter().any(|item| {
-                                let (item_type, item_sub_type) =
-                                    (item.type_().as_str(), item.subtype().as_str());
-
-                                if let Some(file_mime) = mime {
-                                    let (sup_type, sub_type) =
-                                        (file_mime.type_().as_str(), file_mime.subtype().as_str());
-
-                                    if !sup_type.is_empty()
-                                        && sup_type.ne("*")
-                                        && item_type != sup_type
-                                    {
-                                        return false;
-                                    }
-
-                                    if !sub_type.is_empty()
-                                        && sub_type.ne("*")
-                                        && item_sub_type != sub_type
-                                    {
-                                        return false;
-                                    }
-
-                                    true
-                                } else {
-                                    true
-                                }
-                            });
-
-                        if match_file_type {
-                            matched_files.push(entry.path());
-                        }
-                    } else {
-                        matched_files.extend(
-                            get_matched_files(entry.path().as_path().into(), jwt, mime).await?,
-                        );
-                    }
-                }
-            }
-            Err(e) => {
-                error!("get_matched_files() failed: {e}");
-            }
-        }
-
-        Ok(matched_files)
-    }
+    let path = resolve(path)?;
 
-    let root = public_files_root();
-    let mut files = get_matched_files(&root, &jwt, &mime).await?;
-
-    files.shuffle(&mut thread_rng());
-
-    match files.first() {
-        Some(file_path) => {
-            let file_path = &file_path.to_string_lossy()[files_root().to_string_lossy().len()..];
-            let file_path: PathBuf = file_path.into();
+    attempt_access(&path, &jwt, AccessMode::Read)?;
+    assert_file_access(&path)?;
 
-            let uri = if preview.unwrap_or(false) {
-                uri!("/api/v1/files", api_get_preview_file(path = file_path))
-            } else {
-                uri!("/api/v1/files", api_get_raw_file(path = file_path))
-            };
+    let stamp = RenderStamp::of_file(&path);
 
-            Ok(HbpResponse::redirect(uri))
+    if let Some(stamp) = &stamp {
+        if stamp.is_fresh(&validators) {
+            return Ok(HbpResponse::not_modified(stamp)
+                .header("X-Content-Type-Options", "nosniff".to_owned()));
         }
-        None => Err(ApiError::not_found().into()),
     }
-}
 
-#[get("/preview/<path..>")]
-async fn api_get_preview_file(jwt: Option<AuthPayload>, path: PathBuf) -> HbpResult<HbpResponse> {
-    let path = files_root().join(path);
-
-    attempt_access(&path, &jwt)?;
-    assert_file_access(&path)?;
+    // * A stale `If-Range` means the client's partial copy is outdated, so it gets everything
+    let is_range_valid = match (&range_headers.if_range, &stamp) {
+        (Some(if_range), Some(stamp)) => stamp.matches_if_range(if_range),
+        (Some(_), None) => false,
+        (None, _) => true,
+    };
+    let len = path.metadata()?.len();
+    let byte_range = if is_range_valid {
+        ByteRange::of(range_headers.range.as_deref(), len)
+    } else {
+        ByteRange::Full
+    };
 
-    let thumbnail = create_thumbnail(&path)?;
+    let file_name = filename.unwrap_or_else(|| {
+        path.file_name()
+            .map(|file_name| file_name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+    // ? Uploaded markup is downloaded & sandboxed, whatever `disposition` asks for
+    let is_active = is_active_content(&path);
+    let disposition = match is_active {
+        true => Disposition::Attachment,
+        false => disposition.unwrap_or_default(),
+    };
+    let content_disposition = content_disposition_of(disposition, &file_name);
 
-    Ok(HbpResponse::temp_file(thumbnail))
-}
+    let response = match byte_range {
+        ByteRange::Full => HbpResponse::file(path),
+        ByteRange::Partial { start, end } => HbpResponse::file_range(path, start, end),
+        ByteRange::Unsatisfiable => HbpResponse::from_status(StatusCode::RangeNotSatisfiable)
+            .header("Content-Range", format!("bytes */{len}")),
+    };
 
-#[get("/raw/<path..>", rank = 2)]
-async fn api_get_raw_file(jwt: Option<AuthPayload>, path: PathBuf) -> HbpResult<HbpResponse> {
-    let path = files_root().join(path);
+    let response = match is_active {
+        true => response.header("Content-Security-Policy", "sandbox".to_owned()),
+        false => response,
+    };
 
-    attempt_access(&path, &jwt)?;
-    assert_file_access(&path)?;
+    Ok(response
+        .header("Accept-Ranges", "bytes".to_owned())
+        .header("Content-Disposition", content_disposition)
+        .header("X-Content-Type-Options", "nosniff".to_owned())
+        .stamped(stamp.as_ref()))
+}
 
-    Ok(HbpResponse::file(path))
+pub fn files_routes() -> Vec<Route> {
+    routes![files_gallery]
 }
 
 pub fn files_api_routes() -> Vec<Route> {
     routes![
         api_get_raw_file,
         api_get_preview_file,
+        api_warm_up_previews,
         api_get_random_file,
-        api_get_directory
+        api_get_directory,
+        api_upload_files,
+        api_upload_stream,
+        api_make_directory,
+        api_move,
+        api_copy,
+        api_delete_file,
+        api_delete_directory,
+        api_get_trash,
+        api_restore_trashed,
+        api_purge_trashed
     ]
 }
+
+#[cfg(test)]
+mod files_tests {
+    use crate::utils::env::public_files_root;
+    use rocket::local::blocking::Client;
+    use rocket::routes;
+    use std::fs;
+
+    #[test]
+    fn serve_markup_as_sandboxed_downloads() {
+        fs::create_dir_all(public_files_root()).unwrap();
+        let dir = tempfile::Builder::new()
+            .prefix("raw-")
+            .tempdir_in(public_files_root())
+            .unwrap();
+        fs::write(dir.path().join("page.html"), "<script>alert(1)</script>").unwrap();
+        fs::write(dir.path().join("notes.txt"), "notes").unwrap();
+
+        let client =
+            Client::tracked(rocket::build().mount("/", routes![super::api_get_raw_file])).unwrap();
+        let dir_name = dir.path().file_name().unwrap().to_string_lossy();
+
+        let res = client
+            .get(format!(
+                "/raw/public/{dir_name}/page.html?disposition=inline"
+            ))
+            .dispatch();
+        let headers = res.headers();
+        assert!(headers
+            .get_one("Content-Disposition")
+            .unwrap()
+            .starts_with("attachment;"));
+        assert_eq!(headers.get_one("Content-Security-Policy"), Some("sandbox"));
+        assert_eq!(headers.get_one("X-Content-Type-Options"), Some("nosniff"));
+
+        let res = client
+            .get(format!("/raw/public/{dir_name}/notes.txt"))
+            .dispatch();
+        let headers = res.headers();
+        assert!(headers
+            .get_one("Content-Disposition")
+            .unwrap()
+            .starts_with("inline;"));
+        assert_eq!(headers.get_one("Content-Security-Policy"), None);
+        assert_eq!(headers.get_one("X-Content-Type-Options"), Some("nosniff"));
+    }
+}
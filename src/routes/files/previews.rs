@@ -0,0 +1,114 @@
+use rocket::http::ContentType;
+use rocket::tokio::task::spawn_blocking;
+use rocket::{get, post};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::shared::{ApiItem, PreviewWarmUp};
+use crate::utils::auth::AuthPayload;
+use crate::utils::guards::headers::CacheValidators;
+use crate::utils::has_preview;
+use crate::utils::images::thumbnail_width_of;
+use crate::utils::previews::{preview_of, warm_up_previews, Preview};
+use crate::utils::render_cache::RenderStamp;
+use crate::utils::responders::{HbpApiResult, HbpContent, HbpJson, HbpResponse, HbpResult};
+use crate::utils::thumbnails::{ThumbnailFormat, ThumbnailSize};
+
+use super::{
+    assert_directory_access, assert_file_access, attempt_access, relative_path_of, resolve,
+    AccessMode,
+};
+
+const PREVIEW_WIDTH: u32 = 512;
+
+#[get("/preview/<path..>?<w>&<size>&<format>")]
+pub(super) async fn api_get_preview_file(
+    jwt: Option<AuthPayload>,
+    path: PathBuf,
+    w: Option<u32>,
+    size: Option<ThumbnailSize>,
+    format: Option<ThumbnailFormat>,
+    validators: CacheValidators,
+) -> HbpResult<HbpResponse> {
+    let path = resolve(path)?;
+
+    attempt_access(&path, &jwt, AccessMode::Read)?;
+    assert_file_access(&path)?;
+
+    let width = match (size, w) {
+        (Some(size), _) => size.width(),
+        (None, Some(w)) => thumbnail_width_of(Some(w)),
+        (None, None) => PREVIEW_WIDTH,
+    };
+    let format = format.unwrap_or_else(|| ThumbnailFormat::of_source(&path));
+
+    let preview = spawn_blocking({
+        let path = path.clone();
+        move || preview_of(&path, width, format)
+    })
+    .await??;
+    // * SVG previews are derived from the source alone, so its stamp doubles as theirs
+    let stamp = match &preview {
+        Preview::Thumbnail(thumbnail) => RenderStamp::of_file(thumbnail),
+        Preview::Svg(_) => RenderStamp::of_file(&path),
+    };
+
+    if let Some(stamp) = &stamp {
+        if stamp.is_fresh(&validators) {
+            return Ok(HbpResponse::not_modified(stamp));
+        }
+    }
+
+    let response = match preview {
+        Preview::Thumbnail(thumbnail) => HbpResponse::file(thumbnail),
+        Preview::Svg(svg) => HbpResponse::ok(Some(HbpContent::Bytes(
+            svg.into_bytes(),
+            Box::new(Some(ContentType::SVG)),
+        ))),
+    };
+
+    Ok(response.stamped(stamp.as_ref()))
+}
+
+#[post("/preview/warm/<path..>?<size>&<format>")]
+pub(super) async fn api_warm_up_previews(
+    jwt: AuthPayload,
+    path: PathBuf,
+    size: Option<ThumbnailSize>,
+    format: Option<ThumbnailFormat>,
+) -> HbpApiResult<PreviewWarmUp> {
+    let jwt = Some(jwt);
+    let path = resolve(path)?;
+
+    attempt_access(&path, &jwt, AccessMode::Read)?;
+    assert_directory_access(&path)?;
+
+    if let Some(format) = format {
+        format.assert_encodable()?;
+    }
+
+    let images = fs::read_dir(&path)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| resolve(relative_path_of(&entry.path())).ok())
+        .filter(|image| {
+            image.is_file()
+                && has_preview(image)
+                && attempt_access(image, &jwt, AccessMode::Read).is_ok()
+        })
+        .collect::<Vec<_>>();
+    let widths = match size {
+        Some(size) => vec![size.width()],
+        None => ThumbnailSize::ALL.map(ThumbnailSize::width).to_vec(),
+    };
+
+    let warm_up = PreviewWarmUp {
+        path: relative_path_of(&path),
+        images: images.len(),
+        widths: widths.clone(),
+    };
+
+    // * Fire and forget, failures are only logged
+    spawn_blocking(move || warm_up_previews(images, widths, format));
+
+    Ok(HbpJson::Item(ApiItem::accepted(warm_up)))
+}
@@ -0,0 +1,351 @@
+use log::error;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rocket::fairing::AdHoc;
+use rocket::http::Method;
+use rocket::tokio::task::spawn_blocking;
+use rocket::{get, uri, State};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::shared::interfaces::ApiError;
+use crate::shared::{ApiList, RandomFile};
+use crate::utils::auth::AuthPayload;
+use crate::utils::downloads::Disposition;
+use crate::utils::env::public_files_root;
+use crate::utils::responders::{HbpResponse, HbpResult};
+use crate::utils::thumbnails::{ThumbnailFormat, ThumbnailSize};
+
+use super::{attempt_access, matches_mime, mime_of, relative_path_of, resolve, AccessMode};
+
+const FILE_INDEX_TTL: Duration = Duration::from_secs(5 * 60);
+const MAX_RANDOM_COUNT: usize = 100;
+// * Random probes per requested file before falling back to a full scan of the range
+const PICK_ATTEMPTS: usize = 32;
+
+#[derive(Debug)]
+struct IndexedFile {
+    // ? Where the file was found, relative to FILES_ROOT, symlinks are NOT resolved
+    path: PathBuf,
+    resolved_path: PathBuf,
+}
+
+struct FileSnapshot {
+    files: Vec<IndexedFile>,
+    built_at: Instant,
+}
+
+// * Every file under the public root, sorted by path so a directory is a contiguous range
+pub struct FileIndex {
+    snapshot: RwLock<Option<Arc<FileSnapshot>>>,
+    ttl: Duration,
+}
+
+impl Default for FileIndex {
+    fn default() -> Self {
+        Self {
+            snapshot: RwLock::new(None),
+            ttl: FILE_INDEX_TTL,
+        }
+    }
+}
+
+impl FileIndex {
+    fn fresh_snapshot(&self) -> Option<Arc<FileSnapshot>> {
+        let snapshot = self.snapshot.read().ok()?;
+
+        snapshot
+            .as_ref()
+            .filter(|snapshot| snapshot.built_at.elapsed() < self.ttl)
+            .cloned()
+    }
+
+    async fn snapshot(&self) -> HbpResult<Arc<FileSnapshot>> {
+        if let Some(snapshot) = self.fresh_snapshot() {
+            return Ok(snapshot);
+        }
+
+        let snapshot = Arc::new(spawn_blocking(build_snapshot).await??);
+
+        if let Ok(mut current) = self.snapshot.write() {
+            *current = Some(snapshot.clone());
+        }
+
+        Ok(snapshot)
+    }
+
+    pub fn invalidate(&self) {
+        if let Ok(mut snapshot) = self.snapshot.write() {
+            *snapshot = None;
+        }
+    }
+}
+
+// * Any successful write through the files API may add, move or remove files
+pub fn file_index_invalidator() -> AdHoc {
+    AdHoc::on_response("Invalidate file index", |request, response| {
+        Box::pin(async move {
+            let is_write = !matches!(request.method(), Method::Get | Method::Head);
+            let is_files_api = request.uri().path().starts_with("/api/v1/files/");
+
+            if is_write && is_files_api && response.status().class().is_success() {
+                if let Some(file_index) = request.rocket().state::<FileIndex>() {
+                    file_index.invalidate();
+                }
+            }
+        })
+    })
+}
+
+fn index_files_in(dir: &Path, files: &mut Vec<IndexedFile>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("index_files_in() failed for {dir:?}: {e}");
+            return;
+        }
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = PathBuf::from(relative_path_of(&entry.path()));
+
+        // * Entries escaping the root (or dangling) are skipped, not fatal
+        let Ok(resolved_path) = resolve(&path) else {
+            continue;
+        };
+        let Ok(metadata) = fs::metadata(&resolved_path) else {
+            continue;
+        };
+
+        if metadata.is_dir() {
+            // ? Symlinked directories may point back to an ancestor
+            let is_symlink = entry
+                .file_type()
+                .map(|file_type| file_type.is_symlink())
+                .unwrap_or(true);
+
+            if !is_symlink {
+                index_files_in(&resolved_path, files);
+            }
+        } else if metadata.is_file() {
+            files.push(IndexedFile {
+                path,
+                resolved_path,
+            });
+        }
+    }
+}
+
+fn build_snapshot() -> HbpResult<FileSnapshot> {
+    let mut files = vec![];
+    index_files_in(
+        &resolve(relative_path_of(&public_files_root()))?,
+        &mut files,
+    );
+
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(FileSnapshot {
+        files,
+        built_at: Instant::now(),
+    })
+}
+
+// ? Component-wise ordering keeps `a/b/*` together, even with `a/b0` around
+fn files_in<'a>(files: &'a [IndexedFile], dir: &Path) -> &'a [IndexedFile] {
+    let start = files.partition_point(|file| file.path.as_path() < dir);
+    let end = files.partition_point(|file| file.path.as_path() < dir || file.path.starts_with(dir));
+
+    &files[start..end]
+}
+
+// * Probing random slots is O(1) per pick while matches are common, sparse ones fall back to a scan
+fn pick_files<'a, T>(
+    candidates: &'a [T],
+    count: usize,
+    rng: &mut StdRng,
+    accept: impl Fn(&T) -> bool,
+) -> Vec<&'a T> {
+    let mut picked = vec![];
+    let mut seen = HashSet::new();
+
+    if candidates.is_empty() {
+        return picked;
+    }
+
+    for _ in 0..count * PICK_ATTEMPTS {
+        if picked.len() == count {
+            return picked;
+        }
+
+        let index = rng.gen_range(0..candidates.len());
+
+        if seen.insert(index) && accept(&candidates[index]) {
+            picked.push(&candidates[index]);
+        }
+    }
+
+    let rest = (0..candidates.len())
+        .filter(|index| !seen.contains(index) && accept(&candidates[*index]))
+        .collect::<Vec<_>>();
+
+    picked.extend(
+        rest.choose_multiple(rng, count - picked.len())
+            .map(|index| &candidates[*index]),
+    );
+
+    picked
+}
+
+fn random_file_of(file_path: &Path) -> RandomFile {
+    RandomFile {
+        path: file_path.to_string_lossy().into_owned(),
+        url: uri!(
+            "/api/v1/files",
+            super::api_get_raw_file(path = file_path, disposition = _, filename = _)
+        )
+        .to_string(),
+        preview_url: uri!(
+            "/api/v1/files",
+            super::api_get_preview_file(path = file_path, w = _, size = _, format = _)
+        )
+        .to_string(),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+#[get("/random/raw?<mime>&<preview>&<seed>&<dir>&<count>")]
+pub(super) async fn api_get_random_file(
+    mime: Option<String>,
+    jwt: Option<AuthPayload>,
+    preview: Option<bool>,
+    seed: Option<u64>,
+    dir: Option<String>,
+    count: Option<usize>,
+    file_index: &State<FileIndex>,
+) -> HbpResult<HbpResponse> {
+    let mime = mime_of(mime)?;
+    let dir = match dir {
+        Some(dir) => PathBuf::from(relative_path_of(&resolve(dir.trim_matches('/'))?)),
+        None => PathBuf::from(relative_path_of(&public_files_root())),
+    };
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let snapshot = file_index.snapshot().await?;
+    let picked_files = pick_files(
+        files_in(&snapshot.files, &dir),
+        count.unwrap_or(1).clamp(1, MAX_RANDOM_COUNT),
+        &mut rng,
+        |file| {
+            let match_file_type = mime
+                .as_ref()
+                .map(|mime| matches_mime(&file.path, mime))
+                .unwrap_or(true);
+
+            match_file_type && attempt_access(&file.resolved_path, &jwt, AccessMode::Read).is_ok()
+        },
+    );
+
+    if count.is_some() {
+        let random_files = picked_files
+            .iter()
+            .map(|file| random_file_of(&file.path))
+            .collect();
+
+        return Ok(HbpResponse::json(ApiList::ok(random_files), None)?);
+    }
+
+    match picked_files.first() {
+        Some(file) => {
+            let file_path = file.path.clone();
+
+            let uri = if preview.unwrap_or(false) {
+                uri!(
+                    "/api/v1/files",
+                    super::api_get_preview_file(path = file_path, w = _, size = _, format = _)
+                )
+            } else {
+                uri!(
+                    "/api/v1/files",
+                    super::api_get_raw_file(path = file_path, disposition = _, filename = _)
+                )
+            };
+
+            Ok(HbpResponse::redirect(uri))
+        }
+        None => Err(ApiError::not_found().into()),
+    }
+}
+
+#[cfg(test)]
+mod random_tests {
+    use super::{files_in, pick_files, IndexedFile};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::path::{Path, PathBuf};
+
+    fn indexed_files_of(paths: &[&str]) -> Vec<IndexedFile> {
+        paths
+            .iter()
+            .map(|path| IndexedFile {
+                path: PathBuf::from(path),
+                resolved_path: PathBuf::from(path),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn scope_to_directories() {
+        let files = indexed_files_of(&[
+            "public/a.jpg",
+            "public/b/c.jpg",
+            "public/b/d/e.jpg",
+            "public/b0.jpg",
+        ]);
+
+        let paths = |dir: &str| {
+            files_in(&files, Path::new(dir))
+                .iter()
+                .map(|file| file.path.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(paths("public/b"), ["public/b/c.jpg", "public/b/d/e.jpg"]);
+        assert_eq!(paths("public").len(), 4);
+        assert!(paths("public/missing").is_empty());
+    }
+
+    #[test]
+    fn reproducible_picks() {
+        let candidates = (0..1000).collect::<Vec<_>>();
+        let pick = |seed| pick_files(&candidates, 5, &mut StdRng::seed_from_u64(seed), |_| true);
+
+        assert_eq!(pick(42), pick(42));
+        assert_ne!(pick(42), pick(43));
+    }
+
+    #[test]
+    fn distinct_and_sparse_picks() {
+        let candidates = (0..1000).collect::<Vec<_>>();
+        let mut rng = StdRng::seed_from_u64(7);
+
+        // * Only 2 matches out of 1000, probing alone would most likely miss them
+        let mut picked = pick_files(&candidates, 5, &mut rng, |n| *n == 3 || *n == 997);
+        picked.sort();
+        assert_eq!(picked, [&3, &997]);
+
+        let mut picked = pick_files(&candidates[..10], 10, &mut rng, |_| true);
+        picked.sort();
+        picked.dedup();
+        assert_eq!(picked.len(), 10);
+
+        assert!(pick_files(&Vec::<u8>::new(), 3, &mut rng, |_| true).is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+use httpstatus::StatusCode;
+use rocket::{get, uri};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::shared::interfaces::ApiError;
+use crate::shared::{DirectoryEntry, FileKind};
+use crate::utils::auth::AuthPayload;
+use crate::utils::downloads::Disposition;
+use crate::utils::env::public_files_root;
+use crate::utils::images::is_image;
+use crate::utils::responders::{HbpError, HbpResponse, HbpResult};
+use crate::utils::template::{IndexLayout, MoveUpUrl, Templater};
+use crate::utils::thumbnails::{ThumbnailFormat, ThumbnailSize};
+use crate::utils::url_encode_path;
+
+use super::{
+    attempt_access, directory_entries_of, relative_path_of, resolve, sort_entries, AccessMode,
+    SortBy, SortOrder,
+};
+
+const SLIDESHOW_BATCH: usize = 20;
+
+#[derive(Serialize, Debug)]
+struct GalleryItem {
+    name: String,
+    url: String,
+    preview_url: String,
+    is_image: bool,
+}
+
+#[derive(Serialize, Debug)]
+struct GalleryTemplate {
+    title: String,
+    has_directories: bool,
+    directories: Vec<GalleryItem>,
+    files: Vec<GalleryItem>,
+    // * The slideshow draws from the public file index, other folders have none
+    slideshow_url: Option<String>,
+}
+
+fn ui_error(e: HbpError) -> HbpError {
+    e.api_error.with_ui().into()
+}
+
+fn gallery_url_of(path: &str) -> String {
+    format!("/files/{}", url_encode_path(path))
+}
+
+fn gallery_item_of(directory_entry: DirectoryEntry) -> GalleryItem {
+    let path = PathBuf::from(&directory_entry.path);
+
+    match directory_entry.kind {
+        FileKind::Directory => GalleryItem {
+            url: gallery_url_of(&directory_entry.path),
+            preview_url: String::new(),
+            is_image: false,
+            name: directory_entry.name,
+        },
+        FileKind::File => GalleryItem {
+            url: uri!(
+                "/api/v1/files",
+                super::api_get_raw_file(path = path.as_path(), disposition = _, filename = _)
+            )
+            .to_string(),
+            preview_url: format!(
+                "{}?size=small",
+                uri!(
+                    "/api/v1/files",
+                    super::api_get_preview_file(path = path.as_path(), w = _, size = _, format = _)
+                )
+            ),
+            is_image: is_image(&path),
+            name: directory_entry.name,
+        },
+    }
+}
+
+fn title_of(path: &Path) -> String {
+    path.file_name()
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "files".to_owned())
+}
+
+#[get("/<path..>")]
+pub(super) async fn files_gallery(
+    path: PathBuf,
+    jwt: Option<AuthPayload>,
+) -> HbpResult<HbpResponse> {
+    // * `/files` alone opens the public folder, the only one anonymous users can read
+    let sub_path = if path.as_os_str().is_empty() {
+        PathBuf::from(relative_path_of(&public_files_root()))
+    } else {
+        path
+    };
+    let resolved_path = resolve(&sub_path).map_err(ui_error)?;
+
+    attempt_access(&resolved_path, &jwt, AccessMode::Read).map_err(ui_error)?;
+
+    if resolved_path.is_file() {
+        return Ok(HbpResponse::redirect(uri!(
+            "/api/v1/files",
+            super::api_get_raw_file(path = sub_path, disposition = _, filename = _)
+        )));
+    }
+
+    if !resolved_path.is_dir() {
+        return Err(ApiError::not_found().with_ui().into());
+    }
+
+    let relative_path = relative_path_of(&resolved_path);
+    let mut entries = directory_entries_of(&resolved_path, &jwt).map_err(ui_error)?;
+    sort_entries(&mut entries, SortBy::Name, SortOrder::Asc);
+
+    let (directories, files) = entries
+        .into_iter()
+        .map(|directory_entry| (directory_entry.kind, gallery_item_of(directory_entry)))
+        .partition::<Vec<_>, _>(|(kind, _)| *kind == FileKind::Directory);
+
+    let gallery = GalleryTemplate {
+        title: title_of(&resolved_path),
+        has_directories: !directories.is_empty(),
+        directories: directories.into_iter().map(|(_, item)| item).collect(),
+        files: files.into_iter().map(|(_, item)| item).collect(),
+        slideshow_url: resolved_path.starts_with(public_files_root()).then(|| {
+            format!(
+                "/api/v1/files/random/raw?mime=image/*&count={SLIDESHOW_BATCH}&dir={}",
+                urlencoding::encode(&relative_path)
+            )
+        }),
+    };
+
+    let layout_data = IndexLayout::default()
+        .moveup_urls(MoveUpUrl::from_path(
+            &Path::new("files").join(&relative_path),
+        ))
+        .title(&gallery.title)
+        .set_auth(jwt);
+    let html = Templater::new("files/gallery.html".into()).to_html_page(gallery, layout_data)?;
+
+    Ok(HbpResponse::html(html, StatusCode::Ok))
+}
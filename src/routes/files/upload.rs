@@ -0,0 +1,422 @@
+use httpstatus::StatusCode;
+use log::error;
+use rocket::data::{ByteUnit, Data, Limits};
+use rocket::form::{Form, FromForm};
+use rocket::fs::TempFile;
+use rocket::{post, State};
+use sled::Db;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+
+use crate::shared::interfaces::ApiError;
+use crate::shared::{ApiItem, ApiList, UploadedFile};
+use crate::utils::auth::AuthPayload;
+use crate::utils::env::files_root;
+use crate::utils::responders::{HbpApiResult, HbpJson, HbpResult};
+use crate::utils::search::SearchIndex;
+
+use super::{
+    attempt_access, conflict_error, reindex, relative_path_of, renamed_path_of, resolve,
+    AccessMode, OnConflict, MAX_RENAME_ATTEMPTS,
+};
+
+const UPLOAD_LIMIT: &str = "upload";
+const DEFAULT_UPLOAD_LIMIT: ByteUnit = ByteUnit::Mebibyte(512);
+#[cfg(unix)]
+const UPLOADED_FILE_MODE: u32 = 0o644;
+
+#[derive(FromForm)]
+pub struct UploadForm<'r> {
+    files: Vec<TempFile<'r>>,
+}
+
+fn staging_file_for(path: &Path) -> HbpResult<NamedTempFile> {
+    let parent = path
+        .parent()
+        .ok_or_else(|| ApiError::bad_request(vec![format!("{path:?} has NO parent directory")]))?;
+
+    std::fs::create_dir_all(parent)?;
+
+    Ok(tempfile::Builder::new()
+        .prefix(".upload-")
+        .tempfile_in(parent)?)
+}
+
+// * The staged file lives next to the target, so persisting it is an atomic rename
+fn persist_upload(
+    staged: NamedTempFile,
+    path: &Path,
+    on_conflict: OnConflict,
+) -> HbpResult<UploadedFile> {
+    let size = staged.as_file().metadata()?.len();
+
+    // ? `tempfile` creates files as 0600, uploads should be readable like any other file
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        staged
+            .as_file()
+            .set_permissions(std::fs::Permissions::from_mode(UPLOADED_FILE_MODE))?;
+    }
+
+    let persisted_path = match on_conflict {
+        OnConflict::Overwrite => {
+            if path.is_dir() {
+                return Err(conflict_error(path).into());
+            }
+
+            staged.persist(path).map_err(|e| e.error)?;
+            path.to_owned()
+        }
+        OnConflict::Fail => match staged.persist_noclobber(path) {
+            Ok(_) => path.to_owned(),
+            Err(e) if e.error.kind() == ErrorKind::AlreadyExists => {
+                return Err(conflict_error(path).into())
+            }
+            Err(e) => return Err(e.error.into()),
+        },
+        OnConflict::Rename => {
+            let mut staged = staged;
+            let mut persisted_path = None;
+
+            for attempt in 0..MAX_RENAME_ATTEMPTS {
+                let candidate = match attempt {
+                    0 => path.to_owned(),
+                    attempt => renamed_path_of(path, attempt),
+                };
+
+                match staged.persist_noclobber(&candidate) {
+                    Ok(_) => {
+                        persisted_path = Some(candidate);
+                        break;
+                    }
+                    Err(e) if e.error.kind() == ErrorKind::AlreadyExists => staged = e.file,
+                    Err(e) => return Err(e.error.into()),
+                }
+            }
+
+            persisted_path.ok_or_else(|| conflict_error(path))?
+        }
+    };
+
+    Ok(UploadedFile {
+        path: relative_path_of(&persisted_path),
+        size,
+    })
+}
+
+fn upload_file_name(temp_file: &TempFile<'_>) -> HbpResult<String> {
+    // * `name()` is sanitized: NO path separators, NO leading dots & NO extension
+    let name = temp_file
+        .name()
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| ApiError::bad_request(vec!["uploaded file has NO name".to_owned()]))?;
+
+    let raw_extension = temp_file
+        .raw_name()
+        .map(|raw_name| raw_name.dangerous_unsafe_unsanitized_raw().as_str())
+        .and_then(|raw_name| Path::new(raw_name).extension())
+        .and_then(|extension| extension.to_str())
+        .filter(|extension| extension.chars().all(|char| char.is_ascii_alphanumeric()))
+        .map(|extension| extension.to_owned());
+
+    let extension = raw_extension.or_else(|| {
+        temp_file
+            .content_type()
+            .and_then(|content_type| content_type.extension())
+            .map(|extension| extension.to_string())
+    });
+
+    Ok(match extension {
+        Some(extension) => format!("{name}.{extension}"),
+        None => name.to_owned(),
+    })
+}
+
+#[post(
+    "/upload/<path..>?<on_conflict>",
+    data = "<form>",
+    format = "multipart/form-data"
+)]
+pub(super) async fn api_upload_files(
+    path: PathBuf,
+    on_conflict: Option<OnConflict>,
+    mut form: Form<UploadForm<'_>>,
+    jwt: AuthPayload,
+    db: &State<Db>,
+    search_index: &State<SearchIndex>,
+) -> HbpApiResult<UploadedFile> {
+    let jwt = Some(jwt);
+    let dir_path = resolve(path)?;
+    attempt_access(&dir_path, &jwt, AccessMode::Write)?;
+
+    if dir_path.is_file() {
+        return Err(ApiError::unprocessable_entity()
+            .append_error(format!(
+                "{} is NOT a directory",
+                relative_path_of(&dir_path)
+            ))
+            .into());
+    }
+
+    if form.files.is_empty() {
+        return Err(ApiError::bad_request(vec!["NO files to upload".to_owned()]).into());
+    }
+
+    let mut uploaded_files = vec![];
+
+    for temp_file in form.files.iter_mut() {
+        let file_path = resolve(relative_path_of(
+            &dir_path.join(upload_file_name(temp_file)?),
+        ))?;
+        // ? A symlink in a writable folder may resolve into a private one
+        attempt_access(&file_path, &jwt, AccessMode::Write)?;
+        let staged = staging_file_for(&file_path)?;
+
+        temp_file.copy_to(staged.path()).await.map_err(|e| {
+            error!("copy_to() failed for {file_path:?}: {e:?}");
+            ApiError::internal_server_error()
+        })?;
+
+        uploaded_files.push(persist_upload(
+            staged,
+            &file_path,
+            on_conflict.unwrap_or_default(),
+        )?);
+    }
+
+    let uploaded_paths = uploaded_files
+        .iter()
+        .map(|uploaded_file| resolve(&uploaded_file.path))
+        .collect::<HbpResult<Vec<_>>>()?;
+    reindex(db, search_index, &uploaded_paths).await;
+
+    Ok(HbpJson::List(ApiList::created(uploaded_files)))
+}
+
+#[post("/upload/<path..>?<on_conflict>", data = "<data>", rank = 2)]
+pub(super) async fn api_upload_stream(
+    path: PathBuf,
+    on_conflict: Option<OnConflict>,
+    data: Data<'_>,
+    limits: &Limits,
+    jwt: AuthPayload,
+    db: &State<Db>,
+    search_index: &State<SearchIndex>,
+) -> HbpApiResult<UploadedFile> {
+    let file_path = resolve(path)?;
+    attempt_access(&file_path, &Some(jwt), AccessMode::Write)?;
+
+    if file_path == files_root() || file_path.is_dir() {
+        return Err(ApiError::unprocessable_entity()
+            .append_error(format!("{} is a directory", relative_path_of(&file_path)))
+            .into());
+    }
+
+    let limit = limits.get(UPLOAD_LIMIT).unwrap_or(DEFAULT_UPLOAD_LIMIT);
+    let staged = staging_file_for(&file_path)?;
+    let written = data.open(limit).into_file(staged.path()).await?;
+
+    if !written.is_complete() {
+        return Err(ApiError::from_message(
+            &format!("upload exceeds the {} limit", limit),
+            StatusCode::PayloadTooLarge,
+        )
+        .into());
+    }
+
+    let uploaded_file = persist_upload(staged, &file_path, on_conflict.unwrap_or_default())?;
+    reindex(db, search_index, &[resolve(&uploaded_file.path)?]).await;
+
+    Ok(HbpJson::Item(ApiItem::created(uploaded_file)))
+}
+
+#[cfg(test)]
+mod upload_tests {
+    use super::{persist_upload, renamed_path_of, OnConflict};
+    use crate::utils::search::SearchIndex;
+    use rocket::{Build, Rocket};
+    use std::fs;
+    use std::path::Path;
+
+    // * Uploads re-index the notes they write, so the search state must be managed
+    fn managed_rocket() -> Rocket<Build> {
+        rocket::build()
+            .manage(sled::Config::new().temporary(true).open().unwrap())
+            .manage(SearchIndex::default())
+    }
+
+    fn staged_in(dir: &Path, content: &str) -> tempfile::NamedTempFile {
+        let staged = tempfile::Builder::new().tempfile_in(dir).unwrap();
+        fs::write(staged.path(), content).unwrap();
+
+        staged
+    }
+
+    #[test]
+    fn rename_keeps_extension() {
+        assert_eq!(
+            renamed_path_of(Path::new("a/photo.jpg"), 2),
+            Path::new("a/photo (2).jpg")
+        );
+        assert_eq!(
+            renamed_path_of(Path::new("a/notes"), 1),
+            Path::new("a/notes (1)")
+        );
+    }
+
+    #[test]
+    fn conflict_policies() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("file.txt");
+
+        let uploaded =
+            persist_upload(staged_in(dir.path(), "one"), &target, OnConflict::Fail).unwrap();
+        assert_eq!(uploaded.size, 3);
+
+        let error = persist_upload(staged_in(dir.path(), "two"), &target, OnConflict::Fail)
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.api_error.status_code,
+            httpstatus::StatusCode::Conflict
+        );
+        assert_eq!(fs::read_to_string(&target).unwrap(), "one");
+
+        persist_upload(staged_in(dir.path(), "three"), &target, OnConflict::Rename).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("file (1).txt")).unwrap(),
+            "three"
+        );
+
+        persist_upload(
+            staged_in(dir.path(), "four"),
+            &target,
+            OnConflict::Overwrite,
+        )
+        .unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "four");
+
+        let leftovers = fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(leftovers, 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuse_symlinks_into_private_folders() {
+        use crate::utils::auth::UserJwt;
+        use crate::utils::constants::cookies::USER_JWT;
+        use crate::utils::env::{files_root, public_files_root};
+        use rocket::http::{ContentType, Cookie, Status};
+        use rocket::local::blocking::Client;
+        use rocket::routes;
+
+        fs::create_dir_all(public_files_root()).unwrap();
+        let private_dir = tempfile::Builder::new()
+            .prefix("private-")
+            .tempdir_in(files_root())
+            .unwrap();
+        let public_dir = tempfile::Builder::new()
+            .prefix("upload-")
+            .tempdir_in(public_files_root())
+            .unwrap();
+
+        let secret = private_dir.path().join("secret.txt");
+        fs::write(&secret, "secret").unwrap();
+        std::os::unix::fs::symlink(&secret, public_dir.path().join("secret.txt")).unwrap();
+
+        let user_jwt = UserJwt {
+            sub: "not-root".to_owned(),
+            ..Default::default()
+        }
+        .sign_jwt()
+        .unwrap();
+        let client =
+            Client::tracked(managed_rocket().mount("/", routes![super::api_upload_files])).unwrap();
+
+        let dir_name = public_dir.path().file_name().unwrap().to_string_lossy();
+        let res = client
+            .post(format!(
+                "/upload/public/{dir_name}?on_conflict=overwrite"
+            ))
+            .header(ContentType::new("multipart", "form-data").with_params(("boundary", "hbp")))
+            .cookie(Cookie::new(USER_JWT, user_jwt))
+            .body(
+                "--hbp\r\nContent-Disposition: form-data; name=\"files\"; filename=\"secret.txt\"\r\n\
+                 Content-Type: text/plain\r\n\r\nowned\r\n--hbp--\r\n",
+            )
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Forbidden);
+        assert_eq!(fs::read_to_string(&secret).unwrap(), "secret");
+    }
+
+    #[test]
+    fn uploaded_markup_is_never_served_inline() {
+        use crate::utils::auth::UserJwt;
+        use crate::utils::constants::cookies::USER_JWT;
+        use crate::utils::env::public_files_root;
+        use rocket::http::{ContentType, Cookie, Status};
+        use rocket::local::blocking::Client;
+        use rocket::routes;
+
+        fs::create_dir_all(public_files_root()).unwrap();
+        let public_dir = tempfile::Builder::new()
+            .prefix("upload-")
+            .tempdir_in(public_files_root())
+            .unwrap();
+        let dir_name = public_dir.path().file_name().unwrap().to_string_lossy();
+
+        let user_jwt = UserJwt {
+            sub: "not-root".to_owned(),
+            ..Default::default()
+        }
+        .sign_jwt()
+        .unwrap();
+        let client = Client::tracked(managed_rocket().mount(
+            "/",
+            routes![
+                super::api_upload_files,
+                super::api_upload_stream,
+                super::super::api_get_raw_file
+            ],
+        ))
+        .unwrap();
+
+        let res = client
+            .post(format!("/upload/public/{dir_name}"))
+            .header(ContentType::new("multipart", "form-data").with_params(("boundary", "hbp")))
+            .cookie(Cookie::new(USER_JWT, user_jwt.clone()))
+            .body(
+                "--hbp\r\nContent-Disposition: form-data; name=\"files\"; filename=\"page.html\"\r\n\
+                 Content-Type: text/html\r\n\r\n<script>alert(1)</script>\r\n--hbp--\r\n",
+            )
+            .dispatch();
+        assert_eq!(res.status(), Status::Created);
+
+        let res = client
+            .post(format!("/upload/public/{dir_name}/image.svg"))
+            .cookie(Cookie::new(USER_JWT, user_jwt))
+            .body(r#"<svg xmlns="http://www.w3.org/2000/svg" onload="alert(1)"/>"#)
+            .dispatch();
+        assert_eq!(res.status(), Status::Created);
+
+        for file_name in ["page.html", "image.svg"] {
+            let res = client
+                .get(format!(
+                    "/raw/public/{dir_name}/{file_name}?disposition=inline"
+                ))
+                .dispatch();
+            let headers = res.headers();
+
+            assert!(headers
+                .get_one("Content-Disposition")
+                .unwrap()
+                .starts_with("attachment;"));
+            assert_eq!(headers.get_one("Content-Security-Policy"), Some("sandbox"));
+            assert_eq!(headers.get_one("X-Content-Type-Options"), Some("nosniff"));
+        }
+    }
+}
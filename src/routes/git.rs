@@ -1,13 +1,20 @@
 use crate::shared::ApiError;
 use httpstatus::StatusCode;
-use rocket::{get, routes, uri, Route};
-use std::{path::PathBuf, process::Command};
+use rocket::{get, routes, uri, Route, State};
+use sled::Db;
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
 
 use crate::utils::{
     responders::{HbpError, HbpResponse, HbpResult},
+    search::SearchIndex,
     template::{IndexLayout, Templater},
 };
 
+const PULLED_REPO: &str = "markdown/users/hbp";
+
 #[get("/")]
 fn git_index() -> HbpResult<HbpResponse> {
     let html =
@@ -16,10 +23,22 @@ fn git_index() -> HbpResult<HbpResponse> {
     Ok(HbpResponse::html(html, StatusCode::Ok))
 }
 
+fn git_output_of(args: &[&str]) -> Option<String> {
+    Command::new("git")
+        .current_dir(PULLED_REPO)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
 #[get("/pull")]
-fn git_pull() -> HbpResult<HbpResponse> {
+async fn git_pull(db: &State<Db>, search_index: &State<SearchIndex>) -> HbpResult<HbpResponse> {
+    let head = git_output_of(&["rev-parse", "HEAD"]);
+
     let output = Command::new("git")
-        .current_dir::<PathBuf>("markdown/users/hbp".into())
+        .current_dir::<PathBuf>(PULLED_REPO.into())
         .arg("pull")
         .arg("--ff-only")
         .output()
@@ -30,6 +49,26 @@ fn git_pull() -> HbpResult<HbpResponse> {
 
     log::info!("git pull: {output:?}");
 
+    // * Only what the pull touched is re-indexed, everything else is still fresh
+    let pulled_files =
+        head.and_then(|head| git_output_of(&["diff", "--name-only", "--relative", &head, "HEAD"]));
+
+    let indexed = match pulled_files {
+        Some(pulled_files) => {
+            let file_paths = pulled_files
+                .lines()
+                .map(|file_path| Path::new(PULLED_REPO).join(file_path))
+                .collect::<Vec<_>>();
+
+            search_index.update(db, &file_paths).await
+        }
+        None => search_index.refresh(db).await,
+    };
+
+    if let Err(e) = indexed {
+        log::error!("git_pull() failed to update the search index: {e:?}");
+    }
+
     Ok(HbpResponse::redirect(uri!("/git", git_index())))
 }
 
@@ -2,32 +2,52 @@ use std::path::PathBuf;
 
 use httpstatus::StatusCode;
 use rocket::response::Redirect;
-use rocket::{get, routes, Route};
+use rocket::{get, routes, Route, State};
+use sled::Db;
 
 use crate::shared::entities::markdown::FsoMarkdown;
 use crate::utils::fso;
+use crate::utils::guards::headers::CacheValidators;
+use crate::utils::render_cache::{RenderCache, RenderStamp};
 use crate::utils::responders::{HbpResponse, HbpResult};
+use crate::utils::search::SearchIndex;
 use crate::utils::template::IndexLayout;
 
 #[get("/README.md")]
-async fn readme_md() -> HbpResult<HbpResponse> {
+async fn readme_md(
+    validators: CacheValidators,
+    db: &State<Db>,
+    render_cache: &State<RenderCache>,
+    search_index: &State<SearchIndex>,
+) -> HbpResult<HbpResponse> {
     let file_path = PathBuf::from("README.md");
+    let stamp = RenderStamp::of(&file_path, "", search_index.version());
+
+    if let Some(stamp) = &stamp {
+        if stamp.is_fresh(&validators) {
+            return Ok(HbpResponse::not_modified(stamp));
+        }
+    }
+
     let markdown_data = FsoMarkdown::from_markdown(&file_path)?;
 
     let html_result = async {
         if fso::is_marp(&markdown_data.content) {
-            fso::render_marp(&markdown_data).await
+            fso::render_marp(&markdown_data, db, render_cache).await
         } else {
             fso::render_markdown(
                 &markdown_data,
                 IndexLayout::from_title(&markdown_data.title),
+                db,
+                render_cache,
+                search_index,
             )
             .await
         }
     };
 
     let html = html_result.await?;
-    Ok(HbpResponse::html(html, StatusCode::Ok))
+    Ok(HbpResponse::html(html, StatusCode::Ok).stamped(stamp.as_ref()))
 }
 
 #[get("/")]
@@ -1,11 +1,22 @@
 use crate::{
-    shared::interfaces::{ApiError, ApiItem},
-    utils::{auth::AuthPayload, responders::HbpApiResult},
+    routes::blogs::{published_blogs, BLOGS_TAGS_URL},
+    shared::interfaces::{ApiError, ApiItem, ApiList},
+    utils::{
+        auth::AuthPayload,
+        fso::{markdowns_in, tag_counts, tagged_with, TagCount},
+        responders::HbpApiResult,
+        search::{self, SearchHit},
+    },
 };
 use async_std::fs::metadata;
 use response_types::*;
-use rocket::get;
-use std::path::PathBuf;
+use rocket::{get, State};
+use sled::Db;
+use std::path::{Path, PathBuf};
+
+use super::{
+    assert_payload_access, can_read_markdown, can_see_draft, markdown_path_from, user_tags_url,
+};
 
 #[get("/users/<username>/<sub_path..>")]
 pub(super) async fn api_user_markdowns(
@@ -35,14 +46,108 @@ pub(super) async fn api_user_markdowns(
     }
 }
 
+#[get("/?<q>")]
+pub(super) async fn api_search_markdowns(
+    q: Option<&str>,
+    jwt: Option<AuthPayload>,
+    db: &State<Db>,
+) -> HbpApiResult<SearchHit> {
+    let hits = search::search(
+        db,
+        q.unwrap_or_default(),
+        |path| can_read_markdown(path, &jwt),
+        |path| can_see_draft(path, &jwt),
+    )
+    .await?;
+
+    Ok(ApiList::ok(hits).into())
+}
+
+#[get("/_tags/blogs")]
+pub(super) async fn api_blog_tags(jwt: Option<AuthPayload>) -> HbpApiResult<TagCount> {
+    let markdowns = published_blogs(&jwt);
+
+    Ok(ApiList::ok(tag_counts(&markdowns, BLOGS_TAGS_URL)).into())
+}
+
+#[get("/_tags/blogs/<tag>")]
+pub(super) async fn api_blog_tagged(
+    tag: &str,
+    jwt: Option<AuthPayload>,
+) -> HbpApiResult<TaggedMarkdown> {
+    let markdowns = tagged_with(published_blogs(&jwt), tag);
+
+    Ok(ApiList::ok(markdowns.into_iter().map(TaggedMarkdown::from).collect()).into())
+}
+
+#[get("/_tags/users/<username>")]
+pub(super) async fn api_user_tags(username: &str, jwt: AuthPayload) -> HbpApiResult<TagCount> {
+    jwt.assert_username(username)?;
+
+    let (_, user_root) = markdown_path_from(username, Path::new(""));
+    jwt.match_path(&user_root, assert_payload_access)?;
+
+    let markdowns = markdowns_in(&user_root);
+
+    Ok(ApiList::ok(tag_counts(&markdowns, &user_tags_url(username))).into())
+}
+
+#[get("/_tags/users/<username>/<tag>")]
+pub(super) async fn api_user_tagged(
+    username: &str,
+    tag: &str,
+    jwt: AuthPayload,
+) -> HbpApiResult<TaggedMarkdown> {
+    jwt.assert_username(username)?;
+
+    let (_, user_root) = markdown_path_from(username, Path::new(""));
+    jwt.match_path(&user_root, assert_payload_access)?;
+
+    let markdowns = tagged_with(markdowns_in(&user_root), tag);
+
+    Ok(ApiList::ok(markdowns.into_iter().map(TaggedMarkdown::from).collect()).into())
+}
+
 mod response_types {
+    use chrono::{DateTime, Utc};
     use serde::{Deserialize, Serialize};
 
+    use crate::shared::entities::markdown::FsoMarkdown;
+
     #[derive(Serialize, Deserialize)]
     pub struct MarkdownItem {
         pub filename: String,
         pub size: u64,
     }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct TaggedMarkdown {
+        pub title: String,
+        pub url: String,
+        pub author: String,
+        pub dob: String,
+        pub tags: Vec<String>,
+        pub modified_at: Option<DateTime<Utc>>,
+        pub word_count: usize,
+        pub reading_minutes: usize,
+        pub excerpt: String,
+    }
+
+    impl From<FsoMarkdown> for TaggedMarkdown {
+        fn from(markdown: FsoMarkdown) -> Self {
+            Self {
+                title: markdown.title,
+                url: markdown.url,
+                author: markdown.author,
+                dob: markdown.dob,
+                tags: markdown.tags.unwrap_or_default(),
+                modified_at: markdown.modified_at,
+                word_count: markdown.word_count,
+                reading_minutes: markdown.reading_minutes,
+                excerpt: markdown.excerpt,
+            }
+        }
+    }
 }
 
 #[cfg(test)]
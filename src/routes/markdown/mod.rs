@@ -7,7 +7,7 @@ mod ui;
 pub use api::*;
 pub use ui::*;
 
-use crate::utils::auth::UserJwt;
+use crate::utils::auth::{AuthPayload, UserJwt};
 
 fn assert_payload_access(payload: &UserJwt, path: &Path) -> bool {
     let prefix = PathBuf::from("markdown")
@@ -19,6 +19,26 @@ fn assert_payload_access(payload: &UserJwt, path: &Path) -> bool {
     path.starts_with(&*prefix)
 }
 
+fn can_read_markdown(path: &Path, jwt: &Option<AuthPayload>) -> bool {
+    if path.starts_with("markdown/blogs") {
+        return true;
+    }
+
+    match jwt {
+        Some(jwt) => jwt.match_path(path, assert_payload_access).is_ok(),
+        None => false,
+    }
+}
+
+// * Same as listings: root sees every draft, users the ones in their own folder
+fn can_see_draft(path: &Path, jwt: &Option<AuthPayload>) -> bool {
+    match jwt {
+        Some(jwt) if jwt.is_root() => true,
+        Some(AuthPayload::User(payload)) => assert_payload_access(payload, path),
+        _ => false,
+    }
+}
+
 fn markdown_path_from(username: &str, sub_path: &Path) -> (String, PathBuf) {
     let file_path = PathBuf::from("markdown")
         .join("users")
@@ -28,15 +48,34 @@ fn markdown_path_from(username: &str, sub_path: &Path) -> (String, PathBuf) {
     (file_path.to_string_lossy().to_string(), file_path)
 }
 
+fn user_tags_url(username: &str) -> String {
+    format!("/markdown/users/{}/_tags", urlencoding::encode(username))
+}
+
 pub fn markdown_routes() -> Vec<Route> {
     routes![
         markdown_file,
         user_markdown_file,
         user_markdown_editor,
-        user_default
+        user_default,
+        user_markdown_tags,
+        user_markdown_tagged,
+        markdown_search,
+        markdown_export,
+        markdown_thumbnail
     ]
 }
 
 pub fn markdown_api_routes() -> Vec<Route> {
-    routes![api_user_markdowns]
+    routes![
+        api_user_markdowns,
+        api_blog_tags,
+        api_blog_tagged,
+        api_user_tags,
+        api_user_tagged
+    ]
+}
+
+pub fn search_api_routes() -> Vec<Route> {
+    routes![api_search_markdowns]
 }
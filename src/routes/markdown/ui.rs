@@ -1,28 +1,45 @@
 use crate::shared::entities::markdown::*;
 use crate::shared::interfaces::ApiError;
-use crate::utils::fso::render_fso_list;
+use crate::utils::downloads::{content_disposition_of, Disposition};
+use crate::utils::export::{self, ExportFormat};
+use crate::utils::fso::{
+    markdowns_in, render_fso_list, render_fso_page, render_tag_list, tag_counts, tagged_with,
+};
+use crate::utils::images::{is_image, thumbnail_width_of};
+use crate::utils::plaintext::render_plaintext;
 use crate::utils::responders::HbpResult;
 use crate::utils::template::{IndexLayout, MoveUpUrl, Templater};
+use crate::utils::thumbnails::{thumbnail_of, ThumbnailFormat};
 
 use crate::utils::{
     auth::AuthPayload,
     fso,
+    guards::headers::CacheValidators,
+    render_cache::{RenderCache, RenderStamp},
     responders::{HbpContent, HbpResponse},
+    search::{self, SearchHit, SearchIndex},
 };
-use async_std::fs;
 use httpstatus::StatusCode;
 use log::*;
+use rocket::http::ContentType;
+use rocket::tokio::task::spawn_blocking;
 use rocket::{get, uri, State};
 use serde::Serialize;
 use sled::Db;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use super::{assert_payload_access, markdown_path_from};
+use super::{
+    assert_payload_access, can_read_markdown, can_see_draft, markdown_path_from, user_tags_url,
+};
 
 #[get("/<sub_path..>", rank = 2)]
 pub(super) async fn markdown_file(
     sub_path: PathBuf,
     jwt: Option<AuthPayload>,
+    validators: CacheValidators,
+    db: &State<Db>,
+    render_cache: &State<RenderCache>,
+    search_index: &State<SearchIndex>,
 ) -> HbpResult<HbpResponse> {
     let file_path = PathBuf::from("markdown").join(sub_path.clone());
 
@@ -32,27 +49,44 @@ pub(super) async fn markdown_file(
 
     if !(fso::is_markdown(&sub_path) || fso::is_plaintext(&sub_path)) {
         return if file_path.is_dir() {
+            let show_drafts = jwt.as_ref().map(|jwt| jwt.is_root()).unwrap_or(false);
             let layout_data = IndexLayout::default()
                 .moveup_urls(MoveUpUrl::from_path(&file_path))
                 .set_auth(jwt)
-                .title(
-                    &file_path
-                        .file_name()
-                        .map(|file_name| file_name.to_string_lossy())
-                        .unwrap_or_else(|| file_path.to_string_lossy()),
-                );
-
-            render_dir(&file_path, layout_data)
+                .title(&file_title_of(&file_path));
+
+            render_dir(&file_path, layout_data, show_drafts)
         } else {
             Ok(HbpResponse::file(file_path))
         };
     }
 
+    let viewer = jwt.as_ref().map(|jwt| jwt.username()).unwrap_or_default();
+    let stamp = RenderStamp::of(&file_path, viewer, search_index.version());
+
+    if let Some(stamp) = &stamp {
+        if stamp.is_fresh(&validators) {
+            return Ok(HbpResponse::not_modified(stamp));
+        }
+    }
+
+    if fso::is_plaintext(&file_path) {
+        let html = render_plaintext(
+            &file_path,
+            IndexLayout::default()
+                .title(&file_title_of(&file_path))
+                .set_auth(jwt)
+                .moveup_urls(MoveUpUrl::from_path(&file_path)),
+        )?;
+
+        return Ok(HbpResponse::html(html, StatusCode::Ok).stamped(stamp.as_ref()));
+    }
+
     let markdown_data = FsoMarkdown::from_markdown(&file_path)?;
 
     let html = async {
         if fso::is_marp(&markdown_data.content) {
-            fso::render_marp(&markdown_data).await
+            fso::render_marp(&markdown_data, db, render_cache).await
         } else {
             fso::render_markdown(
                 &markdown_data,
@@ -60,15 +94,150 @@ pub(super) async fn markdown_file(
                     .title(&markdown_data.title)
                     .set_auth(jwt)
                     .moveup_urls(MoveUpUrl::from_path(&file_path)),
+                db,
+                render_cache,
+                search_index,
             )
             .await
         }
     }
     .await?;
 
+    Ok(HbpResponse::html(html, StatusCode::Ok).stamped(stamp.as_ref()))
+}
+
+#[get("/_search?<q>")]
+pub(super) async fn markdown_search(
+    q: Option<&str>,
+    jwt: Option<AuthPayload>,
+    db: &State<Db>,
+) -> HbpResult<HbpResponse> {
+    #[derive(Serialize, Debug)]
+    struct RenderData {
+        query: String,
+        hits: Vec<SearchHit>,
+        no_hits: bool,
+    }
+
+    let query = q.unwrap_or_default().trim();
+    let hits = search::search(
+        db,
+        query,
+        |path| can_read_markdown(path, &jwt),
+        |path| can_see_draft(path, &jwt),
+    )
+    .await?;
+
+    let html = Templater::new("markdown/search.html".into()).to_html_page(
+        RenderData {
+            query: query.to_owned(),
+            no_hits: !query.is_empty() && hits.is_empty(),
+            hits,
+        },
+        IndexLayout::from_title("Search").set_auth(jwt),
+    )?;
+
     Ok(HbpResponse::html(html, StatusCode::Ok))
 }
 
+#[get("/_export/<sub_path..>?<format>")]
+pub(super) async fn markdown_export(
+    sub_path: PathBuf,
+    format: Option<ExportFormat>,
+    jwt: Option<AuthPayload>,
+) -> HbpResult<HbpResponse> {
+    let file_path = PathBuf::from("markdown").join(sub_path);
+
+    if !can_read_markdown(&file_path, &jwt) {
+        return Err(ApiError::unauthorized().with_ui().into());
+    }
+
+    if !(file_path.is_dir() || fso::is_markdown(&file_path)) {
+        return Err(ApiError::not_found().with_ui().into());
+    }
+
+    let show_drafts = jwt.as_ref().map(|jwt| jwt.is_root()).unwrap_or(false);
+    let markdowns = export::export_markdowns(&file_path, show_drafts)?;
+
+    let title = match (file_path.is_dir(), markdowns.first()) {
+        (false, Some(markdown)) => markdown.title.clone(),
+        _ => file_title_of(&file_path),
+    };
+    let file_stem = file_path
+        .file_stem()
+        .map(|file_stem| file_stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "export".to_owned());
+    let chapters = export::export_chapters(&markdowns);
+
+    let attachment = |extension: &str| {
+        content_disposition_of(Disposition::Attachment, &format!("{file_stem}.{extension}"))
+    };
+
+    match format.unwrap_or_default() {
+        ExportFormat::Html => {
+            let html = export::standalone_html(&title, chapters, false)?;
+
+            Ok(HbpResponse::html(html, StatusCode::Ok)
+                .header("Content-Disposition", attachment("html")))
+        }
+        ExportFormat::Print => {
+            let html = export::standalone_html(&title, chapters, true)?;
+
+            Ok(HbpResponse::html(html, StatusCode::Ok))
+        }
+        ExportFormat::Epub => {
+            let author = markdowns
+                .iter()
+                .map(|markdown| markdown.author.as_str())
+                .find(|author| !author.is_empty())
+                .unwrap_or_default();
+            let epub = export::epub_of(&title, author, chapters)?;
+
+            Ok(HbpResponse::ok(Some(HbpContent::Bytes(
+                epub,
+                Box::new(Some(ContentType::new("application", "epub+zip"))),
+            )))
+            .header("Content-Disposition", attachment("epub")))
+        }
+    }
+}
+
+#[get("/_thumbnail/<sub_path..>?<w>")]
+pub(super) async fn markdown_thumbnail(
+    sub_path: PathBuf,
+    w: Option<u32>,
+    jwt: Option<AuthPayload>,
+) -> HbpResult<HbpResponse> {
+    let file_path = PathBuf::from("markdown").join(sub_path);
+
+    if !can_read_markdown(&file_path, &jwt) {
+        return Err(ApiError::unauthorized().into());
+    }
+
+    if !(file_path.is_file() && is_image(&file_path)) {
+        return Err(ApiError::not_found().into());
+    }
+
+    let thumbnail = spawn_blocking({
+        let file_path = file_path.clone();
+        move || {
+            thumbnail_of(
+                &file_path,
+                thumbnail_width_of(w),
+                ThumbnailFormat::of_source(&file_path),
+            )
+        }
+    })
+    .await??;
+    let cache_control = if file_path.starts_with("markdown/blogs") {
+        "public, max-age=86400"
+    } else {
+        "private, max-age=86400"
+    };
+
+    Ok(HbpResponse::file(thumbnail).header("Cache-Control", cache_control.to_owned()))
+}
+
 #[get("/_edit/<sub_path..>")]
 pub(super) async fn user_markdown_editor(sub_path: PathBuf, _jwt: AuthPayload) -> HbpResponse {
     let _file_path_str = PathBuf::from("markdown").join(sub_path);
@@ -85,7 +254,10 @@ pub(super) async fn user_markdown_file(
     username: &str,
     sub_path: PathBuf,
     jwt: AuthPayload,
+    validators: CacheValidators,
     db: &State<Db>,
+    render_cache: &State<RenderCache>,
+    search_index: &State<SearchIndex>,
 ) -> HbpResult<HbpResponse> {
     jwt.assert_username(username)?;
 
@@ -107,44 +279,99 @@ pub(super) async fn user_markdown_file(
                 .title(&file_path_str)
                 .username(username)
                 .moveup_urls(moveup_urls),
+            true,
         );
     }
 
-    if fso::is_plaintext(&file_path) {
-        let raw_content = fs::read_to_string(file_path.clone()).await.unwrap();
+    if fso::is_markdown(&file_path) || fso::is_plaintext(&file_path) {
+        let stamp = RenderStamp::of(&file_path, username, search_index.version());
 
-        return Ok(HbpResponse::ok({
-            let filename = file_path.file_name().unwrap().to_string_lossy();
+        if let Some(stamp) = &stamp {
+            if stamp.is_fresh(&validators) {
+                return Ok(HbpResponse::not_modified(stamp));
+            }
+        }
 
-            Some(HbpContent::Html(
-                Templater::index()
-                    .to_html(IndexLayout::from_title(&filename).raw_content({
-                        let lines: Vec<_> = raw_content.split(' ').collect();
+        if fso::is_plaintext(&file_path) {
+            let html = render_plaintext(
+                &file_path,
+                IndexLayout::default()
+                    .title(&file_title_of(&file_path))
+                    .username(username)
+                    .moveup_urls(moveup_urls),
+            )?;
 
-                        &lines.join("<br />")
-                    }))
-                    .unwrap(),
-            ))
-        }));
-    }
+            return Ok(HbpResponse::ok(Some(HbpContent::Html(html))).stamped(stamp.as_ref()));
+        }
 
-    if fso::is_markdown(&file_path) {
         let markdown_data = FsoMarkdown::from_markdown(&file_path)?;
         let html = async {
             if fso::is_marp(&markdown_data.content) {
-                fso::render_marp(&markdown_data).await
+                fso::render_marp(&markdown_data, db, render_cache).await
             } else {
-                fso::render_user_markdown(&markdown_data, &jwt, &file_path, db).await
+                fso::render_user_markdown(
+                    &markdown_data,
+                    &jwt,
+                    &file_path,
+                    db,
+                    render_cache,
+                    search_index,
+                )
+                .await
             }
         }
         .await?;
 
-        return Ok(HbpResponse::ok(Some(HbpContent::Html(html))));
+        return Ok(HbpResponse::ok(Some(HbpContent::Html(html))).stamped(stamp.as_ref()));
     }
 
     Ok(HbpResponse::file(file_path))
 }
 
+#[get("/users/<username>/_tags")]
+pub(super) async fn user_markdown_tags(username: &str, jwt: AuthPayload) -> HbpResult<HbpResponse> {
+    jwt.assert_username(username)?;
+
+    let (_, user_root) = markdown_path_from(username, Path::new(""));
+    jwt.match_path(&user_root, assert_payload_access)?;
+
+    let markdowns = markdowns_in(&user_root);
+    let html = render_tag_list(
+        IndexLayout::from_title("Tags")
+            .username(username)
+            .moveup_urls(MoveUpUrl::from_path(&user_root.join("_tags"))),
+        tag_counts(&markdowns, &user_tags_url(username)),
+    )?;
+
+    Ok(HbpResponse::html(html, StatusCode::Ok))
+}
+
+#[get("/users/<username>/_tags/<tag>")]
+pub(super) async fn user_markdown_tagged(
+    username: &str,
+    tag: &str,
+    jwt: AuthPayload,
+) -> HbpResult<HbpResponse> {
+    jwt.assert_username(username)?;
+
+    let (_, user_root) = markdown_path_from(username, Path::new(""));
+    jwt.match_path(&user_root, assert_payload_access)?;
+
+    let markdowns = tagged_with(markdowns_in(&user_root), tag)
+        .into_iter()
+        .map(FsoEntry::from)
+        .collect();
+
+    let html = render_fso_list(
+        IndexLayout::from_title(&format!("#{tag}"))
+            .username(username)
+            .moveup_urls(MoveUpUrl::from_path(&user_root.join("_tags").join(tag))),
+        markdowns,
+    )?;
+
+    Ok(HbpResponse::html(html, StatusCode::Ok))
+}
+
 #[get("/users", rank = 1)]
 pub(super) async fn user_default(jwt: AuthPayload) -> HbpResponse {
     let uri = uri!(
@@ -155,6 +382,14 @@ pub(super) async fn user_default(jwt: AuthPayload) -> HbpResponse {
     HbpResponse::redirect(uri)
 }
 
+fn file_title_of(file_path: &Path) -> String {
+    file_path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy())
+        .unwrap_or_else(|| file_path.to_string_lossy())
+        .into_owned()
+}
+
 #[derive(Serialize, Default)]
 pub struct MarkdownExtraData {
     title: String,
@@ -162,8 +397,19 @@ pub struct MarkdownExtraData {
     og_image: String,
 }
 
-fn render_dir(dir_path: &PathBuf, layout_data: IndexLayout) -> HbpResult<HbpResponse> {
-    let markdowns: Vec<FsoEntry> = fso::from_dir(dir_path)?;
+fn render_dir(
+    dir_path: &PathBuf,
+    layout_data: IndexLayout,
+    show_drafts: bool,
+) -> HbpResult<HbpResponse> {
+    let mut markdowns: Vec<FsoEntry> = fso::from_dir(dir_path)?;
+
+    if !show_drafts {
+        markdowns.retain(|markdown| !fso::is_draft(markdown));
+    }
+
+    fso::sort_by_title(&mut markdowns);
 
-    render_fso_list(layout_data, markdowns).map(|html| HbpResponse::html(html, StatusCode::Ok))
+    render_fso_page(layout_data, markdowns, None, fso::dir_index_html(dir_path)?)
+        .map(|html| HbpResponse::html(html, StatusCode::Ok))
 }
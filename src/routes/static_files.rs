@@ -1,6 +1,21 @@
+use rocket::http::ContentType;
 use rocket::{fs::NamedFile, get};
 use std::path::{Path, PathBuf};
 
+use crate::utils::responders::{HbpContent, HbpResponse, HbpResult};
+use crate::utils::syntax::highlight_css;
+
+#[get("/css/highlight.css")]
+pub fn serve_highlight_css() -> HbpResult<HbpResponse> {
+    let css = highlight_css()?;
+
+    Ok(HbpResponse::ok(Some(HbpContent::Bytes(
+        css.into_bytes(),
+        Box::new(Some(ContentType::CSS)),
+    )))
+    .header("Cache-Control", "public, max-age=86400".to_owned()))
+}
+
 #[get("/<file..>")]
 pub async fn serve(file: PathBuf) -> Option<NamedFile> {
     NamedFile::open(Path::new("static/").join(file)).await.ok()
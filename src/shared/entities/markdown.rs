@@ -1,15 +1,18 @@
 use crate::shared::interfaces::ApiError;
-use crate::utils::responders::HbpResult;
+use crate::utils::images::cover_thumbnail_of;
+use crate::utils::reading::{excerpt_of, reading_minutes, word_count};
+use crate::utils::responders::{HbpError, HbpResult};
 use crate::utils::url_encode_path;
 use anyhow::Result;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
 use httpstatus::StatusCode;
 use mustache::{Data, EncoderError, MapBuilder};
 use regex::Regex;
 use serde::Serialize;
-use std::collections::HashMap;
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Serialize, Default)]
 pub struct FsoMarkdown {
@@ -18,9 +21,23 @@ pub struct FsoMarkdown {
     pub author: String,
     pub content: String,
     pub dob: String,
+    pub published_at: Option<DateTime<Utc>>,
+    pub modified_at: Option<DateTime<Utc>>,
+    pub modified: String,
+    pub word_count: usize,
+    pub reading_minutes: usize,
+    pub excerpt: String,
+    pub draft: bool,
+    pub toc: bool,
     pub tags: Option<Vec<String>>,
     pub cover_image: String,
+    pub cover_image_srcset: String,
     pub url: String,
+    pub extra: BTreeMap<String, Value>,
+    #[serde(skip)]
+    pub file_path: PathBuf,
+    #[serde(skip)]
+    body_start: usize,
 }
 
 impl From<FsoMarkdown> for Data {
@@ -32,7 +49,13 @@ impl From<FsoMarkdown> for Data {
                 .insert("file_name", &markdown.file_name)?
                 .insert("author", &markdown.author)?
                 .insert("cover_image", &markdown.cover_image)?
-                .insert("dob", &markdown.dob)?;
+                .insert("cover_image_srcset", &markdown.cover_image_srcset)?
+                .insert("dob", &markdown.dob)?
+                .insert("modified", &markdown.modified)?
+                .insert("word_count", &markdown.word_count)?
+                .insert("reading_minutes", &markdown.reading_minutes)?
+                .insert("excerpt", &markdown.excerpt)?
+                .insert("extra", &markdown.extra)?;
 
             if let Some(tags) = markdown.tags {
                 map_builder = map_builder.insert_vec("tags", |mut builder| {
@@ -53,7 +76,7 @@ impl From<FsoMarkdown> for Data {
     }
 }
 
-pub fn extract_markdown_header_content(content: &str) -> Option<String> {
+fn extract_header_comment(content: &str) -> Option<(String, usize)> {
     if let Some(header_comment) = Regex::new("<!--((.|\n)*?)-->").ok()?.find(content) {
         if header_comment.start() != 0 {
             None
@@ -63,13 +86,190 @@ pub fn extract_markdown_header_content(content: &str) -> Option<String> {
                 header_comment.end() - "-->".len(),
             );
             let header_content = (content[start..end]).to_string();
-            Some(header_content)
+            Some((header_content, header_comment.end()))
         }
     } else {
         None
     }
 }
 
+fn extract_front_matter(content: &str, fence: &str) -> Option<(String, usize)> {
+    let mut lines = content.split_inclusive('\n');
+
+    let first_line = lines.next()?;
+    if first_line.trim_end() != fence {
+        return None;
+    }
+
+    let header_start = first_line.len();
+    let mut offset = header_start;
+
+    for line in lines {
+        if line.trim_end() == fence {
+            let header_content = content[header_start..offset].to_string();
+            return Some((header_content, offset + line.len()));
+        }
+
+        offset += line.len();
+    }
+
+    None
+}
+
+#[derive(Debug)]
+pub struct MarkdownHeader {
+    pub fields: BTreeMap<String, Value>,
+    pub body_start: usize,
+}
+
+fn malformed_header(msg: String) -> HbpError {
+    ApiError::from_message(
+        &format!("malformed markdown header: {msg}"),
+        StatusCode::UnprocessableEntity,
+    )
+    .into()
+}
+
+fn parse_comment_fields(header_content: &str) -> HbpResult<BTreeMap<String, Value>> {
+    header_content
+        .trim()
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let colon_index = line.find(':').ok_or_else(|| {
+                malformed_header(format!("`{}` does NOT contain a colon", line.trim()))
+            })?;
+
+            Ok((
+                (line[..colon_index]).trim().to_string(),
+                Value::String((line[colon_index + 1..]).trim().to_string()),
+            ))
+        })
+        .collect()
+}
+
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(val) => Value::String(val),
+        toml::Value::Integer(val) => Value::from(val),
+        toml::Value::Float(val) => Value::from(val),
+        toml::Value::Boolean(val) => Value::Bool(val),
+        toml::Value::Datetime(val) => Value::String(val.to_string()),
+        toml::Value::Array(vals) => Value::Array(vals.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, val)| (key, toml_to_json(val)))
+                .collect(),
+        ),
+    }
+}
+
+fn into_fields(value: Value) -> HbpResult<BTreeMap<String, Value>> {
+    match value {
+        Value::Null => Ok(BTreeMap::new()),
+        Value::Object(map) => Ok(map.into_iter().collect()),
+        _ => Err(malformed_header(
+            "front matter MUST be a key/value mapping".to_owned(),
+        )),
+    }
+}
+
+pub fn parse_markdown_header(content: &str) -> HbpResult<Option<MarkdownHeader>> {
+    let bom_len = if content.starts_with('\u{feff}') {
+        '\u{feff}'.len_utf8()
+    } else {
+        0
+    };
+    let content = &content[bom_len..];
+
+    if let Some((header_content, body_start)) = extract_front_matter(content, "---") {
+        let value: Value = serde_yaml::from_str(&header_content)
+            .map_err(|e| malformed_header(format!("invalid YAML: {e}")))?;
+
+        return Ok(Some(MarkdownHeader {
+            fields: into_fields(value)?,
+            body_start: bom_len + body_start,
+        }));
+    }
+
+    if let Some((header_content, body_start)) = extract_front_matter(content, "+++") {
+        let table: toml::Table = toml::from_str(&header_content)
+            .map_err(|e| malformed_header(format!("invalid TOML: {e}")))?;
+
+        return Ok(Some(MarkdownHeader {
+            fields: into_fields(toml_to_json(toml::Value::Table(table)))?,
+            body_start: bom_len + body_start,
+        }));
+    }
+
+    if let Some((header_content, body_start)) = extract_header_comment(content) {
+        return Ok(Some(MarkdownHeader {
+            fields: parse_comment_fields(&header_content)?,
+            body_start: bom_len + body_start,
+        }));
+    }
+
+    Ok(None)
+}
+
+pub const DOB_FORMAT: &str = "%m/%d/%Y";
+
+pub fn parse_dob(dob: &str) -> Option<DateTime<Utc>> {
+    let dob = dob.trim();
+
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(dob) {
+        return Some(date_time.with_timezone(&Utc));
+    }
+
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"] {
+        if let Ok(date_time) = NaiveDateTime::parse_from_str(dob, format) {
+            return Some(Utc.from_utc_datetime(&date_time));
+        }
+    }
+
+    for format in ["%Y-%m-%d", DOB_FORMAT] {
+        if let Ok(date) = NaiveDate::parse_from_str(dob, format) {
+            return date
+                .and_hms_opt(0, 0, 0)
+                .map(|date_time| Utc.from_utc_datetime(&date_time));
+        }
+    }
+
+    None
+}
+
+fn value_to_bool(value: Value) -> bool {
+    match value {
+        Value::Bool(val) => val,
+        val => value_to_string(val).trim().eq_ignore_ascii_case("true"),
+    }
+}
+
+fn value_to_string(value: Value) -> String {
+    match value {
+        Value::String(val) => val,
+        Value::Null => String::new(),
+        val => val.to_string(),
+    }
+}
+
+fn value_to_tags(value: Value) -> Vec<String> {
+    match value {
+        Value::Array(vals) => vals
+            .into_iter()
+            .map(value_to_string)
+            .map(|tag| tag.trim().to_owned())
+            .filter(|tag| !tag.is_empty())
+            .collect(),
+        val => value_to_string(val)
+            .split(',')
+            .map(|tag| tag.trim().to_owned())
+            .filter(|tag| !tag.is_empty())
+            .collect(),
+    }
+}
+
 impl FsoMarkdown {
     pub fn from_markdown(path: &Path) -> HbpResult<FsoMarkdown> {
         if !path.exists() {
@@ -85,46 +285,54 @@ impl FsoMarkdown {
                 .to_string_lossy()
                 .into_owned(),
             url: url_encode_path(&path.to_string_lossy()),
+            file_path: path.to_owned(),
+            toc: true,
             ..FsoMarkdown::default()
         };
 
-        if let Some(header_comment) = extract_markdown_header_content(&markdown.content) {
-            let mut header_map: HashMap<String, String> = header_comment
-                .trim()
-                .split('\n')
-                .map(|line| {
-                    let colon_index = line.find(':').unwrap_or_else(|| {
-                        panic!("header_comment value lines MUST contain a colon")
-                    });
-
-                    (
-                        (line[..colon_index]).trim().to_string(),
-                        (line[colon_index + 1..]).trim().to_string(),
-                    )
-                })
-                .collect();
+        if let Some(header) = parse_markdown_header(&markdown.content)? {
+            let mut fields = header.fields;
+            markdown.body_start = header.body_start;
 
-            markdown.title = if let Some(title) = header_map.remove("title") {
-                title
-            } else {
-                markdown.file_name.clone()
-            };
+            if let Some(title) = fields.remove("title") {
+                markdown.title = value_to_string(title);
+            }
 
-            if let Some(author) = header_map.remove("author") {
-                markdown.author = author;
+            if let Some(author) = fields.remove("author") {
+                markdown.author = value_to_string(author);
             }
 
-            if let Some(tags) = header_map.remove("tags") {
-                markdown.tags = Some(tags.split(',').map(|tag| tag.trim().to_owned()).collect());
+            if let Some(tags) = fields.remove("tags") {
+                markdown.tags = Some(value_to_tags(tags));
             }
 
-            if let Some(cover_image) = header_map.remove("cover_image") {
-                markdown.cover_image = cover_image;
+            if let Some(cover_image) = fields.remove("cover_image") {
+                markdown.cover_image = value_to_string(cover_image);
+
+                if let Some(thumbnail) = cover_thumbnail_of(&markdown.cover_image, path) {
+                    markdown.cover_image = thumbnail.src;
+                    markdown.cover_image_srcset = thumbnail.srcset;
+                }
             }
 
-            if let Some(dob) = header_map.remove("dob") {
-                markdown.dob = dob;
+            if let Some(dob) = fields.remove("dob") {
+                markdown.dob = value_to_string(dob);
+                markdown.published_at = parse_dob(&markdown.dob);
             }
+
+            if let Some(draft) = fields.remove("draft") {
+                markdown.draft = value_to_bool(draft);
+            }
+
+            if let Some(excerpt) = fields.remove("excerpt") {
+                markdown.excerpt = value_to_string(excerpt);
+            }
+
+            if let Some(toc) = fields.remove("toc") {
+                markdown.toc = !value_to_string(toc).trim().eq_ignore_ascii_case("false");
+            }
+
+            markdown.extra = fields;
         }
 
         if markdown.title.is_empty() {
@@ -134,17 +342,36 @@ impl FsoMarkdown {
             };
         }
 
-        if markdown.dob.is_empty() {
+        if markdown.published_at.is_none() && markdown.dob.is_empty() {
             if let Ok(dob) = path.metadata()?.created() {
-                markdown.dob = format!(
-                    "{}",
-                    DateTime::<Utc>::from(dob).date_naive().format("%m/%d/%Y")
-                );
+                markdown.published_at = Some(DateTime::<Utc>::from(dob));
             }
         }
 
+        if let Some(published_at) = markdown.published_at {
+            markdown.dob = published_at.date_naive().format(DOB_FORMAT).to_string();
+        }
+
+        if let Ok(modified_at) = path.metadata()?.modified() {
+            let modified_at = DateTime::<Utc>::from(modified_at);
+
+            markdown.modified = modified_at.date_naive().format(DOB_FORMAT).to_string();
+            markdown.modified_at = Some(modified_at);
+        }
+
+        markdown.word_count = word_count(markdown.body());
+        markdown.reading_minutes = reading_minutes(markdown.word_count);
+
+        if markdown.excerpt.is_empty() {
+            markdown.excerpt = excerpt_of(markdown.body());
+        }
+
         Ok(markdown)
     }
+
+    pub fn body(&self) -> &str {
+        &self.content[self.body_start..]
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -155,7 +382,7 @@ pub struct FsoDirectory {
 
 #[derive(Serialize, Debug)]
 pub enum FsoFileType {
-    Markdown(FsoMarkdown),
+    Markdown(Box<FsoMarkdown>),
     // Plaintext(String),
     Unknown,
 }
@@ -172,7 +399,7 @@ impl FsoFile {
         Self {
             title,
             url,
-            fso_type: FsoFileType::Markdown(fso_markdown),
+            fso_type: FsoFileType::Markdown(Box::new(fso_markdown)),
         }
     }
 
@@ -184,6 +411,11 @@ impl FsoFile {
         }
     }
 }
+impl From<FsoMarkdown> for FsoEntry {
+    fn from(markdown: FsoMarkdown) -> Self {
+        FsoFile::markdown(markdown.title.clone(), markdown.url.clone(), markdown).into()
+    }
+}
 impl From<FsoFile> for FsoEntry {
     fn from(fso_file: FsoFile) -> Self {
         Self::FsoFile(fso_file)
@@ -211,10 +443,13 @@ impl FsoEntry {
 
         if let Some(file_ext) = path.extension().map(|f| f.to_string_lossy()) {
             return match file_ext.to_lowercase().as_str() {
-                "md" => {
-                    let fso_markdown = FsoMarkdown::from_markdown(path).unwrap();
-                    FsoFile::markdown(title, url, fso_markdown)
-                }
+                "md" => match FsoMarkdown::from_markdown(path) {
+                    Ok(fso_markdown) => FsoFile::markdown(title, url, fso_markdown),
+                    Err(e) => {
+                        log::error!("from_markdown() failed for {path:?}: {e:?}");
+                        FsoFile::unknown(title, url)
+                    }
+                },
                 _ => FsoFile::unknown(title, url),
             }
             .into();
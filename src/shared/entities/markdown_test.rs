@@ -1,6 +1,94 @@
-use crate::shared::entities::markdown::extract_markdown_header_content;
+use httpstatus::StatusCode;
+use serde_json::Value;
+
+use crate::shared::entities::markdown::parse_markdown_header;
 
 #[test]
 fn skip_if_no_metadata_comment() {
-    assert_eq!(extract_markdown_header_content(""), None);
+    assert!(parse_markdown_header("").unwrap().is_none());
+    assert!(parse_markdown_header("# Title\n\nBody").unwrap().is_none());
+}
+
+#[test]
+fn parse_html_comment_header() {
+    let content = "<!--\ntitle: Hello\ntags: a, b\n-->\n# Body";
+    let header = parse_markdown_header(content).unwrap().unwrap();
+
+    assert_eq!(header.fields["title"], Value::from("Hello"));
+    assert_eq!(header.fields["tags"], Value::from("a, b"));
+    assert_eq!(&content[header.body_start..], "\n# Body");
+}
+
+#[test]
+fn parse_yaml_front_matter() {
+    let content = "---\ntitle: Hello\ntags: [a, b]\nseries: rust\n---\n# Body";
+    let header = parse_markdown_header(content).unwrap().unwrap();
+
+    assert_eq!(header.fields["title"], Value::from("Hello"));
+    assert_eq!(header.fields["tags"], Value::from(vec!["a", "b"]));
+    assert_eq!(header.fields["series"], Value::from("rust"));
+    assert_eq!(&content[header.body_start..], "# Body");
+}
+
+#[test]
+fn parse_toml_front_matter() {
+    let content = "+++\ntitle = \"Hello\"\ndob = 2023-04-01\n+++\n# Body";
+    let header = parse_markdown_header(content).unwrap().unwrap();
+
+    assert_eq!(header.fields["title"], Value::from("Hello"));
+    assert_eq!(header.fields["dob"], Value::from("2023-04-01"));
+    assert_eq!(&content[header.body_start..], "# Body");
+}
+
+#[test]
+fn malformed_headers_are_unprocessable() {
+    let malformed = [
+        "<!--\ntitle Hello\n-->",
+        "---\ntitle: [unclosed\n---\n",
+        "+++\ntitle = \n+++\n",
+        "---\n- not\n- a mapping\n---\n",
+    ];
+
+    for content in malformed {
+        let e = parse_markdown_header(content).unwrap_err();
+        assert_eq!(e.api_error.status_code, StatusCode::UnprocessableEntity);
+    }
+}
+
+#[test]
+fn parse_dob_formats() {
+    use crate::shared::entities::markdown::parse_dob;
+    use chrono::{Datelike, Timelike};
+
+    for dob in ["04/01/2023", "2023-04-01", "2023-04-01T08:30:00+07:00"] {
+        let date_time = parse_dob(dob).unwrap_or_else(|| panic!("{dob} MUST be parsed"));
+        assert_eq!(
+            (date_time.year(), date_time.month(), date_time.day()),
+            (2023, 4, 1)
+        );
+    }
+
+    assert_eq!(parse_dob("2023-04-01 10:15").unwrap().hour(), 10);
+    assert!(parse_dob("sometime in spring").is_none());
+}
+
+#[test]
+fn convert_extra_fields_to_template_data() {
+    use crate::shared::entities::markdown::FsoMarkdown;
+    use mustache::Data;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("post.md");
+    std::fs::write(&path, "---\ntitle: Hello\nseries: rust\n---\n# Body").unwrap();
+
+    let data = Data::from(FsoMarkdown::from_markdown(&path).unwrap());
+    let Data::Map(fields) = data else {
+        panic!("markdown MUST convert to a map");
+    };
+    let Some(Data::Map(extra)) = fields.get("extra") else {
+        panic!("extra MUST convert to a map");
+    };
+
+    assert!(fields.get("title") == Some(&Data::String("Hello".to_owned())));
+    assert!(extra.get("series") == Some(&Data::String("rust".to_owned())));
 }
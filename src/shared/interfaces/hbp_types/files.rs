@@ -1,6 +1,53 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone)]
-pub struct Directory {
-    pub children: Vec<String>,
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    Directory,
+    File,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DirectoryEntry {
+    pub name: String,
+    pub path: String,
+    pub kind: FileKind,
+    pub size: u64,
+    pub modified_at: Option<DateTime<Utc>>,
+    pub mime: Option<String>,
+    pub has_preview: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UploadedFile {
+    pub path: String,
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FileEntry {
+    pub path: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TrashedFile {
+    pub id: String,
+    pub path: String,
+    pub is_dir: bool,
+    pub trashed_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PreviewWarmUp {
+    pub path: String,
+    pub images: usize,
+    pub widths: Vec<u32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RandomFile {
+    pub path: String,
+    pub url: String,
+    pub preview_url: String,
 }
@@ -12,6 +12,16 @@ where
     #[serde(serialize_with = "status_code_serialize")]
     pub status_code: StatusCode,
     pub items: Vec<T>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<ApiPage>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ApiPage {
+    pub page: usize,
+    pub per_page: usize,
+    pub total_pages: usize,
+    pub total_items: usize,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -35,7 +45,7 @@ pub struct ApiItem<T: Serialize> {
 }
 
 mod more_impls {
-    use super::{ApiError, ApiItem};
+    use super::{ApiError, ApiItem, ApiList, ApiPage};
     use httpstatus::StatusCode;
     use serde::Serialize;
 
@@ -115,6 +125,44 @@ mod more_impls {
                 item,
             }
         }
+
+        pub fn created(item: T) -> ApiItem<T> {
+            ApiItem {
+                status_code: StatusCode::Created,
+                item,
+            }
+        }
+
+        pub fn accepted(item: T) -> ApiItem<T> {
+            ApiItem {
+                status_code: StatusCode::Accepted,
+                item,
+            }
+        }
+    }
+
+    impl<T: Serialize> ApiList<T> {
+        pub fn ok(items: Vec<T>) -> ApiList<T> {
+            ApiList {
+                status_code: StatusCode::Ok,
+                items,
+                page: None,
+            }
+        }
+
+        pub fn created(items: Vec<T>) -> ApiList<T> {
+            ApiList {
+                status_code: StatusCode::Created,
+                items,
+                page: None,
+            }
+        }
+
+        pub fn with_page(mut self, page: ApiPage) -> Self {
+            self.page = Some(page);
+
+            self
+        }
     }
 }
 
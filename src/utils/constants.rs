@@ -6,3 +6,6 @@ pub mod cookies {
     pub const USER_JWT: &str = "user-jwt";
 }
 pub const DEFAULT_JWT_EXPIRES_IN: &str = "24";
+pub const TRUSTED_MARKDOWN_ROOTS: [&str; 2] = ["markdown/blogs", "README.md"];
+pub const TRASH_DIR: &str = ".trash";
+pub const THUMBNAIL_CACHE_DIR: &str = "hbp.thumbnails";
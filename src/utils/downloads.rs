@@ -0,0 +1,169 @@
+use rocket::form::FromFormField;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteRange {
+    Full,
+    Partial { start: u64, end: u64 },
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    // * Only a single range is served, anything else falls back to the full body as RFC 9110 allows
+    pub fn of(range: Option<&str>, len: u64) -> ByteRange {
+        let spec = match range.and_then(|range| {
+            let (unit, spec) = range.trim().split_once('=')?;
+            unit.trim().eq_ignore_ascii_case("bytes").then_some(spec)
+        }) {
+            Some(spec) if !spec.contains(',') => spec.trim(),
+            _ => return ByteRange::Full,
+        };
+
+        let (start, end) = match spec.split_once('-') {
+            Some((start, end)) => (start.trim(), end.trim()),
+            None => return ByteRange::Full,
+        };
+
+        let parse = |bound: &str| match bound {
+            "" => Ok(None),
+            bound => bound.parse::<u64>().map(Some),
+        };
+
+        match (parse(start), parse(end)) {
+            (Ok(None), Ok(Some(suffix))) => {
+                if suffix == 0 || len == 0 {
+                    ByteRange::Unsatisfiable
+                } else {
+                    ByteRange::Partial {
+                        start: len.saturating_sub(suffix),
+                        end: len - 1,
+                    }
+                }
+            }
+            (Ok(Some(start)), Ok(end)) => {
+                if end.map(|end| end < start).unwrap_or(false) {
+                    ByteRange::Full
+                } else if start >= len {
+                    ByteRange::Unsatisfiable
+                } else {
+                    ByteRange::Partial {
+                        start,
+                        end: end.unwrap_or(u64::MAX).min(len - 1),
+                    }
+                }
+            }
+            _ => ByteRange::Full,
+        }
+    }
+}
+
+#[derive(FromFormField, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Disposition {
+    #[default]
+    Inline,
+    Attachment,
+}
+
+// * Markup can run scripts in the origin of the JWT cookies, so it is never rendered inline
+pub fn is_active_content(path: &Path) -> bool {
+    mime_guess::from_path(path).iter().any(|mime| {
+        matches!(
+            mime.essence_str(),
+            "text/html"
+                | "application/xhtml+xml"
+                | "image/svg+xml"
+                | "text/xml"
+                | "application/xml"
+        ) || mime.suffix() == Some(mime_guess::mime::XML)
+    })
+}
+
+pub fn content_disposition_of(disposition: Disposition, file_name: &str) -> String {
+    let file_name = file_name
+        .chars()
+        .filter(|char| !char.is_control() && !matches!(char, '/' | '\\'))
+        .collect::<String>();
+    // ? Old clients only read `filename`, so it gets an ASCII-only fallback
+    let ascii_file_name = file_name
+        .chars()
+        .map(|char| match char {
+            '"' => '\'',
+            char if char.is_ascii() => char,
+            _ => '_',
+        })
+        .collect::<String>();
+
+    format!(
+        "{}; filename=\"{ascii_file_name}\"; filename*=UTF-8''{}",
+        match disposition {
+            Disposition::Inline => "inline",
+            Disposition::Attachment => "attachment",
+        },
+        urlencoding::encode(&file_name)
+    )
+}
+
+#[cfg(test)]
+mod downloads_tests {
+    use super::{content_disposition_of, is_active_content, ByteRange, Disposition};
+    use std::path::Path;
+
+    fn range_of(range: &str) -> ByteRange {
+        ByteRange::of(Some(range), 1000)
+    }
+
+    fn partial(start: u64, end: u64) -> ByteRange {
+        ByteRange::Partial { start, end }
+    }
+
+    #[test]
+    fn parse_byte_ranges() {
+        assert_eq!(range_of("bytes=0-499"), partial(0, 499));
+        assert_eq!(range_of("bytes=500-"), partial(500, 999));
+        assert_eq!(range_of("bytes=-200"), partial(800, 999));
+        assert_eq!(range_of("bytes=-5000"), partial(0, 999));
+        assert_eq!(range_of("bytes=900-5000"), partial(900, 999));
+        assert_eq!(range_of("Bytes = 1-1"), partial(1, 1));
+    }
+
+    #[test]
+    fn unsatisfiable_byte_ranges() {
+        assert_eq!(range_of("bytes=1000-"), ByteRange::Unsatisfiable);
+        assert_eq!(range_of("bytes=-0"), ByteRange::Unsatisfiable);
+        assert_eq!(ByteRange::of(Some("bytes=0-"), 0), ByteRange::Unsatisfiable);
+    }
+
+    #[test]
+    fn ignore_unsupported_byte_ranges() {
+        assert_eq!(ByteRange::of(None, 1000), ByteRange::Full);
+        assert_eq!(range_of("bytes=0-1,5-9"), ByteRange::Full);
+        assert_eq!(range_of("items=0-1"), ByteRange::Full);
+        assert_eq!(range_of("bytes=9-1"), ByteRange::Full);
+        assert_eq!(range_of("bytes=a-b"), ByteRange::Full);
+        assert_eq!(range_of("bytes=5"), ByteRange::Full);
+        assert_eq!(range_of("bytes=-"), ByteRange::Full);
+    }
+
+    #[test]
+    fn content_dispositions() {
+        assert_eq!(
+            content_disposition_of(Disposition::Inline, "a.mp4"),
+            "inline; filename=\"a.mp4\"; filename*=UTF-8''a.mp4"
+        );
+        assert_eq!(
+            content_disposition_of(Disposition::Attachment, "Ảnh \"1\"\r\n/.jpg"),
+            "attachment; filename=\"_nh '1'.jpg\"; filename*=UTF-8''%E1%BA%A2nh%20%221%22.jpg"
+        );
+    }
+
+    #[test]
+    fn active_contents() {
+        for file_name in ["a.html", "a.HTM", "a.xhtml", "a.svg", "a.xml", "a.rss"] {
+            assert!(is_active_content(Path::new(file_name)), "{file_name}");
+        }
+
+        for file_name in ["a.txt", "a.md", "a.png", "a.pdf", "Makefile"] {
+            assert!(!is_active_content(Path::new(file_name)), "{file_name}");
+        }
+    }
+}
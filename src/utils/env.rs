@@ -12,6 +12,7 @@ pub enum EnvKey {
     RootUser,
     DeployEnv,
     SneuUiRoot,
+    PublicUrl,
 }
 
 pub fn from_env(env_key: EnvKey) -> &'static str {
@@ -33,6 +34,7 @@ pub fn from_env(env_key: EnvKey) -> &'static str {
         EnvKey::RootUser => dotenv!("ROOT_USER"),
         EnvKey::DeployEnv => dotenv!("DEPLOY_ENV"),
         EnvKey::SneuUiRoot => dotenv!("SNEU_UI_ROOT"),
+        EnvKey::PublicUrl => dotenv!("PUBLIC_URL"),
     }
 }
 
@@ -42,6 +44,9 @@ pub fn public_files_root() -> PathBuf {
 pub fn files_root() -> PathBuf {
     from_env(EnvKey::FilesRoot).into()
 }
+pub fn trash_root() -> PathBuf {
+    files_root().join(constants::TRASH_DIR)
+}
 pub fn jwt_secret() -> Vec<u8> {
     let key = from_env(EnvKey::JwtSecret);
 
@@ -0,0 +1,469 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::Utc;
+use log::error;
+use pulldown_cmark::escape::escape_html;
+use regex::{Captures, Regex};
+use rocket::FromFormField;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::shared::entities::markdown::FsoMarkdown;
+
+use super::fso::{is_markdown, markdown_to_html, markdowns_in, DIR_INDEX_FILE};
+use super::images::{image_root_of, local_image_of};
+use super::responders::HbpResult;
+use super::sanitize::{sanitize_html, sanitize_html_of};
+use super::syntax::highlight_css;
+use super::template::Templater;
+
+const IMG_SRC_PATTERN: &str = r#"(<img\b[^>]*?\ssrc=")([^"]*)(")"#;
+const VOID_ELEMENT_PATTERN: &str = r"<(area|br|col|hr|img|input|source|wbr)\b([^>]*?)\s*/?>";
+const EXPORT_CSS_PATH: &str = "static/css/export.css";
+
+#[derive(FromFormField, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    #[default]
+    Html,
+    Print,
+    Epub,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExportChapter {
+    pub id: String,
+    pub title: String,
+    pub html: String,
+    #[serde(skip)]
+    file_path: PathBuf,
+    // * Images outside of it are NOT embedded
+    #[serde(skip)]
+    image_root: PathBuf,
+}
+
+#[derive(Serialize, Debug)]
+struct ExportContents {
+    entries: Vec<ExportContentsEntry>,
+}
+
+#[derive(Serialize, Debug)]
+struct ExportContentsEntry {
+    id: String,
+    title: String,
+}
+
+#[derive(Serialize, Debug)]
+struct ExportTemplate<'a> {
+    title: &'a str,
+    css: String,
+    print: bool,
+    contents: Option<ExportContents>,
+    chapters: &'a [ExportChapter],
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    let _ = escape_html(&mut escaped, text);
+
+    escaped
+}
+
+pub fn export_markdowns(path: &Path, show_drafts: bool) -> HbpResult<Vec<FsoMarkdown>> {
+    if !path.is_dir() {
+        return Ok(vec![FsoMarkdown::from_markdown(path)?]);
+    }
+
+    let mut markdowns: Vec<FsoMarkdown> = markdowns_in(path)
+        .into_iter()
+        .filter(|markdown| show_drafts || !markdown.draft)
+        .collect();
+
+    markdowns.sort_by(|a, b| {
+        a.file_path
+            .parent()
+            .cmp(&b.file_path.parent())
+            .then(a.published_at.cmp(&b.published_at))
+            .then(a.file_name.cmp(&b.file_name))
+    });
+
+    let index_path = path.join(DIR_INDEX_FILE);
+    if index_path.is_file() && is_markdown(&index_path) {
+        markdowns.insert(0, FsoMarkdown::from_markdown(&index_path)?);
+    }
+
+    Ok(markdowns)
+}
+
+pub fn export_chapters(markdowns: &[FsoMarkdown]) -> Vec<ExportChapter> {
+    markdowns
+        .iter()
+        .enumerate()
+        .map(|(index, markdown)| ExportChapter {
+            id: format!("chapter-{}", index + 1),
+            title: markdown.title.clone(),
+            html: sanitize_html_of(&markdown.file_path, markdown_to_html(markdown.body())),
+            file_path: markdown.file_path.clone(),
+            image_root: image_root_of(&markdown.file_path),
+        })
+        .collect()
+}
+
+fn rewrite_images(
+    chapter: &ExportChapter,
+    mut rewrite: impl FnMut(&Path) -> Option<String>,
+) -> String {
+    let img_src_regex = Regex::new(IMG_SRC_PATTERN).expect("valid img src regex");
+    let base_dir = chapter.file_path.parent().unwrap_or(Path::new(""));
+
+    img_src_regex
+        .replace_all(&chapter.html, |captures: &Captures| {
+            let src = &captures[2];
+
+            let rewritten =
+                local_image_of(&src.replace("&amp;", "&"), base_dir, &chapter.image_root)
+                    .and_then(|image_path| rewrite(&image_path))
+                    .unwrap_or_else(|| src.to_owned());
+
+            format!("{}{rewritten}{}", &captures[1], &captures[3])
+        })
+        .into_owned()
+}
+
+fn mime_of(path: &Path) -> String {
+    mime_guess::from_path(path)
+        .first_or_octet_stream()
+        .essence_str()
+        .to_owned()
+}
+
+pub fn inline_images(chapter: &ExportChapter) -> String {
+    rewrite_images(chapter, |image_path| {
+        let bytes = fs::read(image_path)
+            .map_err(|e| error!("read image {image_path:?} failed: {e:?}"))
+            .ok()?;
+
+        Some(format!(
+            "data:{};base64,{}",
+            mime_of(image_path),
+            BASE64.encode(bytes)
+        ))
+    })
+}
+
+fn export_css() -> String {
+    let highlight_css = highlight_css()
+        .map_err(|e| error!("highlight_css() failed: {e:?}"))
+        .unwrap_or_default();
+
+    let export_css = fs::read_to_string(EXPORT_CSS_PATH)
+        .map_err(|e| error!("read {EXPORT_CSS_PATH} failed: {e:?}"))
+        .unwrap_or_default();
+
+    [export_css, highlight_css].join("\n")
+}
+
+pub fn standalone_html(
+    title: &str,
+    chapters: Vec<ExportChapter>,
+    print: bool,
+) -> HbpResult<String> {
+    let chapters: Vec<ExportChapter> = chapters
+        .into_iter()
+        .map(|chapter| ExportChapter {
+            html: inline_images(&chapter),
+            ..chapter
+        })
+        .collect();
+
+    let contents = (chapters.len() > 1).then(|| ExportContents {
+        entries: chapters
+            .iter()
+            .map(|chapter| ExportContentsEntry {
+                id: chapter.id.clone(),
+                title: chapter.title.clone(),
+            })
+            .collect(),
+    });
+
+    Templater::new("markdown/export.html".into()).to_html(ExportTemplate {
+        title,
+        css: export_css(),
+        print,
+        contents,
+        chapters: &chapters,
+    })
+}
+
+pub fn to_xhtml(html: &str) -> String {
+    let void_element_regex = Regex::new(VOID_ELEMENT_PATTERN).expect("valid void element regex");
+
+    void_element_regex
+        .replace_all(&sanitize_html(html), "<$1$2 />")
+        .replace("&nbsp;", "&#160;")
+}
+
+fn xhtml_document(title: &str, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+<title>{}</title>
+<link rel="stylesheet" type="text/css" href="style.css" />
+</head>
+<body>
+{body}
+</body>
+</html>
+"#,
+        escape(title)
+    )
+}
+
+pub fn epub_of(title: &str, author: &str, chapters: Vec<ExportChapter>) -> HbpResult<Vec<u8>> {
+    let mut images: HashMap<PathBuf, String> = HashMap::new();
+    let mut manifest_items = vec![];
+
+    let chapter_documents: Vec<(String, String)> = chapters
+        .iter()
+        .map(|chapter| {
+            let html = rewrite_images(chapter, |image_path| {
+                let image_count = images.len();
+                let href = images.entry(image_path.to_owned()).or_insert_with(|| {
+                    let extension = image_path
+                        .extension()
+                        .map(|extension| format!(".{}", extension.to_string_lossy()))
+                        .unwrap_or_default();
+
+                    format!("images/image-{}{extension}", image_count + 1)
+                });
+
+                Some(href.clone())
+            });
+
+            let file_name = format!("{}.xhtml", chapter.id);
+            let body = format!(
+                r#"<section id="{}">{}</section>"#,
+                chapter.id,
+                to_xhtml(&html)
+            );
+
+            (file_name, xhtml_document(&chapter.title, &body))
+        })
+        .collect();
+
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(b"application/epub+zip")?;
+
+    zip.start_file("META-INF/container.xml", deflated)?;
+    zip.write_all(
+        br#"<?xml version="1.0" encoding="utf-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml" />
+  </rootfiles>
+</container>
+"#,
+    )?;
+
+    zip.start_file("OEBPS/style.css", deflated)?;
+    zip.write_all(export_css().as_bytes())?;
+
+    for (file_name, document) in &chapter_documents {
+        zip.start_file(format!("OEBPS/{file_name}"), deflated)?;
+        zip.write_all(document.as_bytes())?;
+    }
+
+    let mut images: Vec<(PathBuf, String)> = images.into_iter().collect();
+    images.sort_by(|a, b| a.1.cmp(&b.1));
+
+    for (index, (image_path, href)) in images.iter().enumerate() {
+        zip.start_file(format!("OEBPS/{href}"), stored)?;
+        zip.write_all(&fs::read(image_path)?)?;
+
+        manifest_items.push(format!(
+            r#"<item id="image-{}" href="{}" media-type="{}" />"#,
+            index + 1,
+            escape(href),
+            mime_of(image_path)
+        ));
+    }
+
+    let nav_entries: String = chapters
+        .iter()
+        .map(|chapter| {
+            format!(
+                r#"<li><a href="{}.xhtml">{}</a></li>"#,
+                chapter.id,
+                escape(&chapter.title)
+            )
+        })
+        .collect();
+
+    zip.start_file("OEBPS/nav.xhtml", deflated)?;
+    zip.write_all(
+        xhtml_document(
+            title,
+            &format!(r#"<nav epub:type="toc" id="toc"><ol>{nav_entries}</ol></nav>"#),
+        )
+        .as_bytes(),
+    )?;
+
+    for chapter in &chapters {
+        manifest_items.push(format!(
+            r#"<item id="{0}" href="{0}.xhtml" media-type="application/xhtml+xml" />"#,
+            chapter.id
+        ));
+    }
+
+    let spine_items: String = chapters
+        .iter()
+        .map(|chapter| format!(r#"<itemref idref="{}" />"#, chapter.id))
+        .collect();
+
+    let identifier = {
+        let mut hasher = DefaultHasher::new();
+        title.hash(&mut hasher);
+        chapters
+            .iter()
+            .for_each(|chapter| chapter.file_path.hash(&mut hasher));
+
+        format!("urn:hbp:{:016x}", hasher.finish())
+    };
+
+    zip.start_file("OEBPS/content.opf", deflated)?;
+    zip.write_all(
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="book-id">{identifier}</dc:identifier>
+    <dc:title>{}</dc:title>
+    <dc:creator>{}</dc:creator>
+    <dc:language>en</dc:language>
+    <meta property="dcterms:modified">{}</meta>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav" />
+    <item id="style" href="style.css" media-type="text/css" />
+    {}
+  </manifest>
+  <spine>{spine_items}</spine>
+</package>
+"#,
+            escape(title),
+            escape(author),
+            Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+            manifest_items.join("\n    "),
+        )
+        .as_bytes(),
+    )?;
+
+    Ok(zip.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod export_tests {
+    use super::{epub_of, inline_images, to_xhtml, ExportChapter};
+    use std::fs;
+    use std::io::Read;
+    use std::path::{Path, PathBuf};
+    use zip::{CompressionMethod, ZipArchive};
+
+    fn chapter_in(root: &Path, dir: &Path, html: &str) -> ExportChapter {
+        ExportChapter {
+            id: "chapter-1".to_owned(),
+            title: "Chapter <1>".to_owned(),
+            html: html.to_owned(),
+            file_path: dir.join("note.md"),
+            image_root: root.to_owned(),
+        }
+    }
+
+    fn markdown_dir() -> (tempfile::TempDir, PathBuf) {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("notes");
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a b.png"), [137, 80, 78, 71]).unwrap();
+
+        (root, dir)
+    }
+
+    #[test]
+    fn inline_relative_images_only() {
+        let (root, dir) = markdown_dir();
+        let html = inline_images(&chapter_in(
+            root.path(),
+            &dir,
+            r#"<img src="a%20b.png" alt="a"><img src="../../../Cargo.toml"><img src="https://x.y/z.png">"#,
+        ));
+
+        assert!(html.contains(r#"<img src="data:image/png;base64,iVBORw==" alt="a">"#));
+        assert!(html.contains(r#"<img src="../../../Cargo.toml">"#));
+        assert!(html.contains(r#"<img src="https://x.y/z.png">"#));
+    }
+
+    #[test]
+    fn xhtml_closes_void_elements() {
+        assert_eq!(
+            to_xhtml("<p>a<br>b\u{a0}<img src=\"x.png\"></p><hr />"),
+            "<p>a<br />b&#160;<img src=\"x.png\" /></p><hr />"
+        );
+    }
+
+    #[test]
+    fn epub_package_layout() {
+        let (root, dir) = markdown_dir();
+        let epub = epub_of(
+            "Notes & more",
+            "hbp",
+            vec![chapter_in(
+                root.path(),
+                &dir,
+                r#"<p><img src="a%20b.png"></p>"#,
+            )],
+        )
+        .unwrap();
+
+        let mut archive = ZipArchive::new(std::io::Cursor::new(epub)).unwrap();
+
+        let mimetype = archive.by_index(0).unwrap();
+        assert_eq!(mimetype.name(), "mimetype");
+        assert_eq!(mimetype.compression(), CompressionMethod::Stored);
+        drop(mimetype);
+
+        let mut chapter = String::new();
+        archive
+            .by_name("OEBPS/chapter-1.xhtml")
+            .unwrap()
+            .read_to_string(&mut chapter)
+            .unwrap();
+        assert!(chapter.contains("<title>Chapter &lt;1&gt;</title>"));
+        assert!(chapter.contains(r#"<img src="images/image-1.png" />"#));
+
+        let mut opf = String::new();
+        archive
+            .by_name("OEBPS/content.opf")
+            .unwrap()
+            .read_to_string(&mut opf)
+            .unwrap();
+        assert!(opf.contains("<dc:title>Notes &amp; more</dc:title>"));
+        assert!(opf.contains(r#"href="images/image-1.png" media-type="image/png""#));
+
+        assert!(archive.by_name("OEBPS/images/image-1.png").is_ok());
+        assert!(archive.by_name("OEBPS/nav.xhtml").is_ok());
+        assert!(archive.by_name("META-INF/container.xml").is_ok());
+    }
+}
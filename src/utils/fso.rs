@@ -1,26 +1,40 @@
+use crate::data::models::search_doc::SearchDoc;
 use crate::data::models::tiny_url::TinyUrl;
+use crate::data::search_orm::SearchOrm;
 use crate::data::tiny_url_orm::TinyUrlOrm;
 use crate::shared::entities::markdown::*;
 use crate::shared::interfaces::ApiError;
 use crate::utils::template::Templater;
+use chrono::{DateTime, Utc};
 use httpstatus::StatusCode::BadRequest;
 use log::error;
 use nanoid::nanoid;
-use pulldown_cmark::{html, Options, Parser};
+use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, Options, Parser, Tag};
 use rocket::uri;
+use serde::{Deserialize, Serialize};
 use sled::Db;
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::read_dir;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use crate::routes::tiny_urls::rocket_uri_macro_serve_tiny_url;
 
 use super::auth::{AuthPayload, ResourseJwt};
+use super::images::responsive_images;
 use super::marper;
+use super::math::{extract_math, mermaid_html, render_math, MathSpan};
+use super::pagination::Pagination;
+use super::render_cache::{RenderCache, RenderKind};
 use super::responders::HbpResult;
+use super::sanitize::{is_trusted, sanitize_html_of};
+use super::search::SearchIndex;
+use super::syntax::{highlight_code, lang_of};
 use super::template::{IndexLayout, MarkdownTemplate, MoveUpUrl};
+use super::toc::{anchor_headings, toc_of, Toc};
+use super::wiki::{link_wiki_pages, resolve_wiki_links, wiki_root_of, Backlinks, WikiIndex};
 
-pub fn markdown_to_html(markdown: &str) -> String {
+pub fn markdown_options() -> Options {
     let mut options = Options::empty();
     options.insert(Options::ENABLE_STRIKETHROUGH);
     options.insert(Options::ENABLE_TABLES);
@@ -29,9 +43,69 @@ pub fn markdown_to_html(markdown: &str) -> String {
     options.insert(Options::ENABLE_TASKLISTS);
     options.insert(Options::ENABLE_HEADING_ATTRIBUTES);
 
-    let parser = Parser::new_ext(markdown, options);
+    options
+}
+
+// ? Headings must read the same for the TOC as for their anchors, wiki links & math included
+fn rewritten_events<'a>(markdown: &'a str, math_spans: &[MathSpan]) -> Vec<Event<'a>> {
+    let parser = Parser::new_ext(markdown, markdown_options());
+
+    render_math(
+        link_wiki_pages(markdown, parser.into_offset_iter()),
+        math_spans,
+    )
+}
+
+pub fn markdown_toc(markdown: &str) -> Option<Toc> {
+    let (markdown, math_spans) = extract_math(markdown);
+
+    toc_of(&rewritten_events(&markdown, &math_spans))
+}
+
+pub fn markdown_to_html(markdown: &str) -> String {
+    let mut events = vec![];
+    let mut code_block: Option<(CowStr, Vec<Event>)> = None;
+
+    let (markdown, math_spans) = extract_math(markdown);
+
+    for event in rewritten_events(&markdown, &math_spans) {
+        match (event, code_block.as_mut()) {
+            (Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))), None) => {
+                code_block = Some((info, vec![]));
+            }
+            (Event::End(Tag::CodeBlock(kind)), Some(_)) => {
+                if let Some((info, code_events)) = code_block.take() {
+                    let code: String = code_events
+                        .iter()
+                        .filter_map(|event| match event {
+                            Event::Text(text) => Some(text.as_ref()),
+                            _ => None,
+                        })
+                        .collect();
+
+                    let lang = lang_of(&info);
+                    let highlighted = match lang {
+                        "mermaid" => Some(mermaid_html(&code)),
+                        lang => highlight_code(&code, lang),
+                    };
+
+                    match highlighted {
+                        Some(highlighted) => events.push(Event::Html(highlighted.into())),
+                        None => {
+                            events.push(Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))));
+                            events.extend(code_events);
+                            events.push(Event::End(Tag::CodeBlock(kind)));
+                        }
+                    }
+                }
+            }
+            (event, Some((_, code_events))) => code_events.push(event),
+            (event, None) => events.push(event),
+        }
+    }
+
     let mut html = String::new();
-    html::push_html(&mut html, parser);
+    html::push_html(&mut html, anchor_headings(events).into_iter());
 
     html
 }
@@ -49,7 +123,11 @@ pub fn is_plaintext(file_path: &Path) -> bool {
     }
 }
 
-pub async fn render_marp(markdown: &FsoMarkdown) -> HbpResult<String> {
+pub async fn render_marp(
+    markdown: &FsoMarkdown,
+    db: &Db,
+    render_cache: &RenderCache,
+) -> HbpResult<String> {
     if !marper::is_marp(&markdown.content) {
         return Err(ApiError::from_message(
             &format!("NOT a marp: {}", markdown.file_name),
@@ -58,17 +136,89 @@ pub async fn render_marp(markdown: &FsoMarkdown) -> HbpResult<String> {
         .into());
     }
 
-    marper::render_marp(&markdown.content).await
+    let file_path = &markdown.file_path;
+    if let Some(html) = render_cache.get(db, file_path, RenderKind::Marp).await {
+        return Ok(html);
+    }
+
+    let html = marper::render_marp(&markdown.content, is_trusted(file_path)).await?;
+    render_cache
+        .insert(db, file_path, RenderKind::Marp, &html)
+        .await;
+
+    Ok(html)
+}
+
+async fn cached_markdown_html(
+    markdown: &FsoMarkdown,
+    db: &Db,
+    render_cache: &RenderCache,
+) -> String {
+    let file_path = &markdown.file_path;
+    if let Some(html) = render_cache.get(db, file_path, RenderKind::Markdown).await {
+        return html;
+    }
+
+    let html = responsive_images(
+        &sanitize_html_of(file_path, markdown_to_html(markdown.body())),
+        file_path,
+    );
+    render_cache
+        .insert(db, file_path, RenderKind::Markdown, &html)
+        .await;
+
+    html
 }
 pub fn is_marp(content: &str) -> bool {
     marper::is_marp(content)
 }
+
+// ? The cached HTML only depends on the file, links are resolved against the current index
+async fn linked_markdown_html(
+    markdown: &FsoMarkdown,
+    db: &Db,
+    render_cache: &RenderCache,
+    search_index: &SearchIndex,
+) -> HbpResult<(String, Option<Backlinks>)> {
+    let wiki_index = match wiki_root_of(&markdown.file_path) {
+        Some(wiki_root) => search_index.wiki_index_of(db, &wiki_root).await?,
+        None => WikiIndex::empty().into(),
+    };
+
+    let markdown_html = cached_markdown_html(markdown, db, render_cache).await;
+    let backlinks = wiki_index.backlinks_of(&markdown.file_path);
+
+    Ok((resolve_wiki_links(&markdown_html, &wiki_index), backlinks))
+}
+
+async fn series_of(markdown: &FsoMarkdown, db: &Db) -> HbpResult<Option<Series>> {
+    let dir_path = match markdown.file_path.parent() {
+        Some(dir_path) => dir_path,
+        None => return Ok(None),
+    };
+    let docs = SearchOrm::default()
+        .find_docs(db, &dir_path.to_string_lossy())
+        .await?;
+
+    Ok(series_in(&markdown.file_path, &docs))
+}
+
 pub async fn render_markdown(
     markdown: &FsoMarkdown,
     layout_data: IndexLayout,
+    db: &Db,
+    render_cache: &RenderCache,
+    search_index: &SearchIndex,
 ) -> HbpResult<String> {
-    Templater::new("markdown/markdown.html".into())
-        .to_html_page(MarkdownTemplate::of(markdown, None), layout_data)
+    let (markdown_html, backlinks) =
+        linked_markdown_html(markdown, db, render_cache, search_index).await?;
+
+    Templater::new("markdown/markdown.html".into()).to_html_page(
+        MarkdownTemplate::of(markdown, markdown_html, None)
+            .series(series_of(markdown, db).await?)
+            .backlinks(backlinks),
+        layout_data,
+    )
 }
 
 fn allowed_glob(file_path: &Path) -> String {
@@ -91,6 +241,8 @@ pub async fn render_user_markdown(
     jwt: &AuthPayload,
     file_path: &Path,
     db: &Db,
+    render_cache: &RenderCache,
+    search_index: &SearchIndex,
 ) -> HbpResult<String> {
     let layout_data = IndexLayout::default()
         .title(&markdown.title)
@@ -127,12 +279,30 @@ pub async fn render_user_markdown(
             .unwrap_or_default()
     };
 
+    let (markdown_html, backlinks) =
+        linked_markdown_html(markdown, db, render_cache, search_index).await?;
+
     Templater::new("markdown/markdown.html".into()).to_html_page(
-        MarkdownTemplate::of(markdown, Some(signed_url)),
+        MarkdownTemplate::of(markdown, markdown_html, Some(signed_url))
+            .series(series_of(markdown, db).await?)
+            .backlinks(backlinks),
         layout_data,
     )
 }
 
+pub const DIR_INDEX_FILE: &str = "index.md";
+
+fn is_dir_index(file_path: &Path) -> bool {
+    file_path
+        .file_name()
+        .map(|file_name| {
+            file_name
+                .to_string_lossy()
+                .eq_ignore_ascii_case(DIR_INDEX_FILE)
+        })
+        .unwrap_or(false)
+}
+
 pub fn from_dir<P: AsRef<Path>>(path: &P) -> HbpResult<Vec<FsoEntry>> {
     let markdowns = read_dir(path)
         .map_err(|e| {
@@ -141,6 +311,7 @@ pub fn from_dir<P: AsRef<Path>>(path: &P) -> HbpResult<Vec<FsoEntry>> {
         })?
         .filter_map(|entry| {
             let entry = entry.ok()?;
+            let entry_path = entry.path();
             let entry_filename = entry.file_name();
             let entry_filename = entry_filename.to_string_lossy();
 
@@ -148,12 +319,21 @@ pub fn from_dir<P: AsRef<Path>>(path: &P) -> HbpResult<Vec<FsoEntry>> {
                 return None;
             }
 
-            let entry_ext = entry_filename.split('.').last().map(|ext| ext.to_lowercase());
+            if entry_path.is_dir() {
+                return Some(FsoEntry::from_path(&entry_path));
+            }
+
+            if is_dir_index(&entry_path) {
+                return None;
+            }
+
+            let entry_ext = entry_filename
+                .split('.')
+                .last()
+                .map(|ext| ext.to_lowercase());
 
             match entry_ext.as_deref() {
-                Some("md" | "txt") => {
-                    Some(FsoEntry::from_path(&entry.path()))
-                },
+                Some("md" | "txt") => Some(FsoEntry::from_path(&entry_path)),
                 _ => None,
             }
         })
@@ -162,9 +342,283 @@ pub fn from_dir<P: AsRef<Path>>(path: &P) -> HbpResult<Vec<FsoEntry>> {
     Ok(markdowns)
 }
 
+pub fn dir_index_html(dir_path: &Path) -> HbpResult<Option<String>> {
+    let index_path = read_dir(dir_path)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|entry_path| entry_path.is_file() && is_dir_index(entry_path));
+
+    match index_path {
+        Some(index_path) => {
+            let markdown = FsoMarkdown::from_markdown(&index_path)?;
+            let html = sanitize_html_of(&index_path, markdown_to_html(markdown.body()));
+
+            Ok(Some(responsive_images(&html, &index_path)))
+        }
+        None => Ok(None),
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct SeriesLink {
+    pub title: String,
+    pub url: String,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct Series {
+    pub prev: Option<SeriesLink>,
+    pub next: Option<SeriesLink>,
+}
+
+// * Siblings come from the search index, the other files of the folder are NOT read per render
+fn series_in(file_path: &Path, docs: &[SearchDoc]) -> Option<Series> {
+    let dir_path = file_path.parent()?;
+
+    let mut siblings: Vec<&SearchDoc> = docs
+        .iter()
+        .filter(|doc| {
+            let doc_path = Path::new(&doc.path);
+
+            doc_path.parent() == Some(dir_path)
+                && is_markdown(doc_path)
+                && !is_dir_index(doc_path)
+                && (!doc.draft || doc_path == file_path)
+        })
+        .collect();
+
+    siblings.sort_by(|a, b| {
+        a.published_at.cmp(&b.published_at).then(
+            Path::new(&a.path)
+                .file_name()
+                .cmp(&Path::new(&b.path).file_name()),
+        )
+    });
+
+    let index = siblings
+        .iter()
+        .position(|sibling| Path::new(&sibling.path) == file_path)?;
+
+    let link_at = |index: usize| {
+        siblings.get(index).map(|sibling| SeriesLink {
+            title: sibling.title.clone(),
+            url: sibling.url.clone(),
+        })
+    };
+
+    let series = Series {
+        prev: index.checked_sub(1).and_then(link_at),
+        next: link_at(index + 1),
+    };
+
+    if series.prev.is_none() && series.next.is_none() {
+        None
+    } else {
+        Some(series)
+    }
+}
+
 pub fn render_fso_list(layout_data: IndexLayout, markdowns: Vec<FsoEntry>) -> HbpResult<String> {
+    render_fso_page(layout_data, markdowns, None, None)
+}
+
+pub fn render_fso_page(
+    layout_data: IndexLayout,
+    markdowns: Vec<FsoEntry>,
+    pagination: Option<Pagination>,
+    index_html: Option<String>,
+) -> HbpResult<String> {
+    #[derive(Serialize, Debug)]
+    struct RenderData {
+        markdowns: Vec<FsoEntry>,
+        pagination: Option<Pagination>,
+        index_html: Option<String>,
+    }
+
+    Templater::new("markdown/list.html".into()).to_html_page(
+        RenderData {
+            markdowns,
+            pagination,
+            index_html,
+        },
+        layout_data,
+    )
+}
+
+fn published_at(entry: &FsoEntry) -> Option<DateTime<Utc>> {
+    match entry {
+        FsoEntry::FsoFile(FsoFile {
+            fso_type: FsoFileType::Markdown(markdown),
+            ..
+        }) => markdown.published_at,
+        _ => None,
+    }
+}
+
+pub fn is_draft(entry: &FsoEntry) -> bool {
+    matches!(
+        entry,
+        FsoEntry::FsoFile(FsoFile {
+            fso_type: FsoFileType::Markdown(markdown),
+            ..
+        }) if markdown.draft
+    )
+}
+
+pub fn sort_by_published(entries: &mut [FsoEntry]) {
+    entries.sort_by_key(|entry| {
+        (
+            matches!(entry, FsoEntry::FsoFile(_)),
+            Reverse(published_at(entry)),
+        )
+    });
+}
+
+pub fn sort_by_title(entries: &mut [FsoEntry]) {
+    entries.sort_by_cached_key(|entry| match entry {
+        FsoEntry::FsoDirectory(directory) => (false, directory.title.to_lowercase()),
+        FsoEntry::FsoFile(file) => (true, file.title.to_lowercase()),
+    });
+}
+
+pub fn markdown_files_in(dir_path: &Path) -> Vec<PathBuf> {
+    let entries = match read_dir(dir_path) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .flat_map(|entry| {
+            let path = entry.path();
+
+            if path.is_dir() {
+                markdown_files_in(&path)
+            } else if is_markdown(&path) || is_plaintext(&path) {
+                vec![path]
+            } else {
+                vec![]
+            }
+        })
+        .collect()
+}
+
+pub fn markdowns_in(dir_path: &Path) -> Vec<FsoMarkdown> {
+    markdown_files_in(dir_path)
+        .iter()
+        .filter(|file_path| is_markdown(file_path) && !is_dir_index(file_path))
+        .filter_map(|file_path| {
+            FsoMarkdown::from_markdown(file_path)
+                .map_err(|e| error!("from_markdown() failed for {file_path:?}: {e:?}"))
+                .ok()
+        })
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+    pub url: String,
+}
+
+pub fn tag_counts(markdowns: &[FsoMarkdown], base_url: &str) -> Vec<TagCount> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+
+    for tag in markdowns
+        .iter()
+        .flat_map(|markdown| markdown.tags.iter().flatten())
+    {
+        *counts.entry(tag).or_default() += 1;
+    }
+
+    let mut tag_counts: Vec<TagCount> = counts
+        .into_iter()
+        .map(|(tag, count)| TagCount {
+            tag: tag.to_owned(),
+            count,
+            url: format!("{base_url}/{}", urlencoding::encode(tag)),
+        })
+        .collect();
+
+    tag_counts.sort_by(|a, b| b.count.cmp(&a.count).then(a.tag.cmp(&b.tag)));
+
+    tag_counts
+}
+
+pub fn tagged_with(markdowns: Vec<FsoMarkdown>, tag: &str) -> Vec<FsoMarkdown> {
+    markdowns
+        .into_iter()
+        .filter(|markdown| {
+            markdown
+                .tags
+                .iter()
+                .flatten()
+                .any(|markdown_tag| markdown_tag.eq(tag))
+        })
+        .collect()
+}
+
+pub fn render_tag_list(layout_data: IndexLayout, tag_counts: Vec<TagCount>) -> HbpResult<String> {
     let mut render_data = HashMap::new();
-    render_data.insert("markdowns", markdowns);
+    render_data.insert("tags", tag_counts);
+
+    Templater::new("markdown/tags.html".into()).to_html_page(render_data, layout_data)
+}
 
-    Templater::new("markdown/list.html".into()).to_html_page(render_data, layout_data)
+#[cfg(test)]
+mod fso_tests {
+    use super::series_in;
+    use crate::data::models::search_doc::SearchDoc;
+    use std::path::Path;
+
+    fn doc_of(path: &str, published_at: Option<i64>, draft: bool) -> SearchDoc {
+        SearchDoc {
+            path: path.to_owned(),
+            url: path.to_owned(),
+            title: path.to_owned(),
+            tags: vec![],
+            mtime: 0,
+            draft,
+            published_at,
+            terms: Default::default(),
+            links: vec![],
+            version: 0,
+        }
+    }
+
+    #[test]
+    fn series_from_indexed_siblings() {
+        let docs = [
+            doc_of("markdown/blogs/rust/p3.md", Some(3), false),
+            doc_of("markdown/blogs/rust/p1.md", Some(1), false),
+            doc_of("markdown/blogs/rust/draft.md", Some(2), true),
+            doc_of("markdown/blogs/rust/index.md", Some(0), false),
+            doc_of("markdown/blogs/rust/p2.md", Some(2), false),
+            doc_of("markdown/blogs/rust/nested/p0.md", Some(0), false),
+            doc_of("markdown/blogs/rust-2/p1.md", Some(1), false),
+        ];
+        let url_of = |link: Option<super::SeriesLink>| link.map(|link| link.url);
+
+        let series = series_in(Path::new("markdown/blogs/rust/p2.md"), &docs).unwrap();
+        assert_eq!(
+            url_of(series.prev).as_deref(),
+            Some("markdown/blogs/rust/p1.md")
+        );
+        assert_eq!(
+            url_of(series.next).as_deref(),
+            Some("markdown/blogs/rust/p3.md")
+        );
+
+        // ? A draft only shows up in its own series
+        let series = series_in(Path::new("markdown/blogs/rust/draft.md"), &docs).unwrap();
+        assert_eq!(
+            url_of(series.next).as_deref(),
+            Some("markdown/blogs/rust/p2.md")
+        );
+
+        assert!(series_in(Path::new("markdown/blogs/rust-2/p1.md"), &docs).is_none());
+        assert!(series_in(Path::new("markdown/blogs/rust/gone.md"), &docs).is_none());
+    }
 }
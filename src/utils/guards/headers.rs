@@ -1,6 +1,8 @@
+use crate::utils::env::{from_env, EnvKey};
 use crate::{shared::interfaces::ApiError, utils::status_from};
 use httpstatus::StatusCode;
 use rocket::request::{FromRequest, Outcome, Request};
+use std::time::SystemTime;
 
 pub struct Referer(String);
 
@@ -19,3 +21,76 @@ impl<'r> FromRequest<'r> for Referer {
         }
     }
 }
+
+pub struct SiteUrl(pub String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SiteUrl {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        // * `Host` is client controlled, the configured URL wins whenever it is set
+        let public_url = from_env(EnvKey::PublicUrl).trim_end_matches('/');
+
+        if !public_url.is_empty() {
+            return Outcome::Success(SiteUrl(public_url.to_owned()));
+        }
+
+        let host = request
+            .host()
+            .map(|host| host.to_string())
+            .unwrap_or_else(|| "localhost".to_owned());
+
+        let scheme = match request.headers().get_one("X-Forwarded-Proto") {
+            Some(scheme) => scheme.to_owned(),
+            None if host.starts_with("localhost") || host.starts_with("127.") => "http".to_owned(),
+            None => "https".to_owned(),
+        };
+
+        Outcome::Success(SiteUrl(format!("{scheme}://{host}")))
+    }
+}
+
+#[derive(Default)]
+pub struct CacheValidators {
+    pub if_none_match: Option<String>,
+    pub if_modified_since: Option<SystemTime>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CacheValidators {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = request.headers();
+
+        Outcome::Success(CacheValidators {
+            if_none_match: headers.get_one("If-None-Match").map(|etag| etag.to_owned()),
+            if_modified_since: headers
+                .get_one("If-Modified-Since")
+                .and_then(|since| httpdate::parse_http_date(since).ok()),
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct RangeHeaders {
+    pub range: Option<String>,
+    pub if_range: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RangeHeaders {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = request.headers();
+
+        Outcome::Success(RangeHeaders {
+            range: headers.get_one("Range").map(|range| range.to_owned()),
+            if_range: headers
+                .get_one("If-Range")
+                .map(|if_range| if_range.to_owned()),
+        })
+    }
+}
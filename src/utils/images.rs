@@ -0,0 +1,175 @@
+use regex::{Captures, Regex};
+use std::path::{Path, PathBuf};
+
+use super::url_encode_path;
+use super::wiki::wiki_root_of;
+
+pub const MARKDOWN_ROOT: &str = "markdown";
+pub const THUMBNAIL_WIDTHS: [u32; 4] = [320, 640, 1024, 1600];
+pub const DEFAULT_THUMBNAIL_WIDTH: u32 = 1024;
+pub const COVER_THUMBNAIL_WIDTH: u32 = 640;
+
+const IMG_TAG_PATTERN: &str = r"<img\b([^>]*?)\s*/?>";
+const SRC_ATTR_PATTERN: &str = r#"\ssrc="([^"]*)""#;
+const IMAGE_SIZES: &str = "(max-width: 980px) 100vw, 980px";
+
+fn image_root_in(markdown_root: &Path, path: &Path) -> PathBuf {
+    wiki_root_of(path).unwrap_or_else(|| markdown_root.to_owned())
+}
+
+pub fn image_root_of(path: &Path) -> PathBuf {
+    image_root_in(Path::new(MARKDOWN_ROOT), path)
+}
+
+pub fn is_image(path: &Path) -> bool {
+    mime_guess::from_path(path)
+        .first()
+        .map(|mime| mime.type_() == mime_guess::mime::IMAGE)
+        .unwrap_or(false)
+}
+
+pub fn thumbnail_width_of(width: Option<u32>) -> u32 {
+    let width = width.unwrap_or(DEFAULT_THUMBNAIL_WIDTH);
+
+    THUMBNAIL_WIDTHS
+        .into_iter()
+        .find(|thumbnail_width| *thumbnail_width >= width)
+        .unwrap_or(THUMBNAIL_WIDTHS[THUMBNAIL_WIDTHS.len() - 1])
+}
+
+pub fn local_image_of(src: &str, base_dir: &Path, root: &Path) -> Option<PathBuf> {
+    let is_relative = !(src.is_empty()
+        || src.starts_with('/')
+        || src.starts_with('#')
+        || src.contains("://")
+        || src.starts_with("data:"));
+
+    if !is_relative {
+        return None;
+    }
+
+    let src = src.split(['?', '#']).next().unwrap_or_default();
+    let src = urlencoding::decode(src).ok()?;
+    let image_path = base_dir.join(&*src).canonicalize().ok()?;
+
+    (image_path.is_file() && image_path.starts_with(root.canonicalize().ok()?))
+        .then_some(image_path)
+}
+
+pub struct Thumbnail {
+    pub src: String,
+    pub srcset: String,
+}
+
+fn thumbnail_in(
+    markdown_root: &Path,
+    src: &str,
+    file_path: &Path,
+    width: u32,
+) -> Option<Thumbnail> {
+    let base_dir = file_path.parent()?;
+    let image_path = local_image_of(src, base_dir, &image_root_in(markdown_root, file_path))?;
+
+    if !is_image(&image_path) {
+        return None;
+    }
+
+    let markdown_root = markdown_root.canonicalize().ok()?;
+    let sub_path = image_path.strip_prefix(markdown_root).ok()?;
+    let thumbnail_url = format!(
+        "/markdown/_thumbnail/{}",
+        url_encode_path(&sub_path.to_string_lossy())
+    );
+
+    Some(Thumbnail {
+        src: format!("{thumbnail_url}?w={width}"),
+        srcset: THUMBNAIL_WIDTHS
+            .iter()
+            .map(|width| format!("{thumbnail_url}?w={width} {width}w"))
+            .collect::<Vec<_>>()
+            .join(", "),
+    })
+}
+
+pub fn cover_thumbnail_of(cover_image: &str, file_path: &Path) -> Option<Thumbnail> {
+    thumbnail_in(
+        Path::new(MARKDOWN_ROOT),
+        cover_image,
+        file_path,
+        COVER_THUMBNAIL_WIDTH,
+    )
+}
+
+fn responsive_images_in(markdown_root: &Path, html: &str, file_path: &Path) -> String {
+    let img_tag_regex = Regex::new(IMG_TAG_PATTERN).expect("valid img tag regex");
+    let src_attr_regex = Regex::new(SRC_ATTR_PATTERN).expect("valid src attr regex");
+
+    img_tag_regex
+        .replace_all(html, |captures: &Captures| {
+            let mut attrs = captures[1].to_owned();
+
+            let thumbnail = src_attr_regex.captures(&attrs).and_then(|src_captures| {
+                let src = src_captures[1].replace("&amp;", "&");
+                thumbnail_in(markdown_root, &src, file_path, DEFAULT_THUMBNAIL_WIDTH)
+            });
+
+            if let Some(thumbnail) = thumbnail {
+                attrs = src_attr_regex
+                    .replace(
+                        &attrs,
+                        format!(
+                            r#" src="{}" srcset="{}" sizes="{IMAGE_SIZES}""#,
+                            thumbnail.src, thumbnail.srcset
+                        ),
+                    )
+                    .into_owned();
+            }
+
+            if !attrs.contains(" loading=") {
+                attrs.push_str(r#" loading="lazy" decoding="async""#);
+            }
+
+            format!("<img{attrs}>")
+        })
+        .into_owned()
+}
+
+pub fn responsive_images(html: &str, file_path: &Path) -> String {
+    responsive_images_in(Path::new(MARKDOWN_ROOT), html, file_path)
+}
+
+#[cfg(test)]
+mod images_tests {
+    use super::{responsive_images_in, thumbnail_width_of};
+    use std::fs;
+
+    #[test]
+    fn snap_thumbnail_widths() {
+        assert_eq!(thumbnail_width_of(None), 1024);
+        assert_eq!(thumbnail_width_of(Some(1)), 320);
+        assert_eq!(thumbnail_width_of(Some(641)), 1024);
+        assert_eq!(thumbnail_width_of(Some(99999)), 1600);
+    }
+
+    #[test]
+    fn rewrite_local_images_to_thumbnails() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("notes");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a b.jpg"), [0xFF, 0xD8]).unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let html = responsive_images_in(
+            root.path(),
+            r#"<p><img src="a%20b.jpg" alt="a" /><img src="notes.txt"><img src="https://x.y/z.png" loading="eager"></p>"#,
+            &dir.join("note.md"),
+        );
+
+        let thumbnail_url = "/markdown/_thumbnail/notes/a%20b.jpg";
+        assert!(html.contains(&format!(
+            r#"<img src="{thumbnail_url}?w=1024" srcset="{thumbnail_url}?w=320 320w, {thumbnail_url}?w=640 640w, {thumbnail_url}?w=1024 1024w, {thumbnail_url}?w=1600 1600w" sizes="(max-width: 980px) 100vw, 980px" alt="a" loading="lazy" decoding="async">"#
+        )));
+        assert!(html.contains(r#"<img src="notes.txt" loading="lazy" decoding="async">"#));
+        assert!(html.contains(r#"<img src="https://x.y/z.png" loading="eager">"#));
+    }
+}
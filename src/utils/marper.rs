@@ -1,11 +1,26 @@
+use crate::shared::entities::markdown::parse_markdown_header;
 use crate::utils::env::{from_env, EnvKey};
 use crate::utils::marper;
+use log::error;
+use pulldown_cmark::escape::escape_html;
+use regex::Regex;
 use reqwest::{multipart, Client};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
 
+use super::fso::markdown_to_html;
 use super::responders::HbpResult;
+use super::sanitize::sanitize_html;
 use super::template::Templater;
 
+const MARP_THEMES_ROOT: &str = "static/css/marp";
+const DEFAULT_THEME: &str = "default";
+const SLIDE_WIDTH: u32 = 1280;
+const SLIDE_HEIGHT: u32 = 720;
+
 #[derive(Deserialize, Debug)]
 pub struct MarpContent {
     pub html: String,
@@ -13,17 +28,270 @@ pub struct MarpContent {
 }
 
 pub fn is_marp(markdown: &str) -> bool {
-    let parts: Vec<&str> = markdown.split("---").collect();
+    match parse_markdown_header(markdown) {
+        Ok(Some(header)) => match header.fields.get("marp") {
+            Some(Value::Bool(marp)) => *marp,
+            Some(Value::String(marp)) => marp.trim() == "true",
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct SlideDirectives {
+    paginate: bool,
+    header: String,
+    footer: String,
+    background_color: String,
+    color: String,
+    class: String,
+}
+
+impl SlideDirectives {
+    fn apply(&mut self, key: &str, value: &str) -> bool {
+        match key {
+            "paginate" => self.paginate = value == "true",
+            "header" => self.header = value.to_owned(),
+            "footer" => self.footer = value.to_owned(),
+            "backgroundColor" => self.background_color = value.to_owned(),
+            "color" => self.color = value.to_owned(),
+            "class" => self.class = value.to_owned(),
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+fn split_slides(body: &str) -> Vec<String> {
+    let mut slides = vec![String::new()];
+    let mut in_code_block = false;
+
+    for line in body.split_inclusive('\n') {
+        let trimmed_line = line.trim();
+
+        if trimmed_line.starts_with("```") || trimmed_line.starts_with("~~~") {
+            in_code_block = !in_code_block;
+        }
+
+        if !in_code_block && trimmed_line == "---" {
+            slides.push(String::new());
+            continue;
+        }
+
+        if let Some(slide) = slides.last_mut() {
+            slide.push_str(line);
+        }
+    }
+
+    slides
+}
+
+fn loose_field(line: &str) -> Option<(String, String)> {
+    let (key, value) = line.split_once(':')?;
+    let key = key.trim();
+
+    if key.is_empty()
+        || !key
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '_')
+    {
+        return None;
+    }
+
+    let value = value.trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|val| val.strip_suffix('"'))
+        .or_else(|| {
+            value
+                .strip_prefix('\'')
+                .and_then(|val| val.strip_suffix('\''))
+        })
+        .unwrap_or(value);
+
+    Some((key.to_owned(), value.to_owned()))
+}
+
+fn directive_fields(comment: &str) -> Option<Vec<(String, String)>> {
+    comment
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(loose_field)
+        .collect()
+}
+
+fn is_directive_key(key: &str) -> bool {
+    SlideDirectives::default().apply(key.trim_start_matches('_'), "")
+}
+
+fn extract_directives(slide: &str, directives: &mut SlideDirectives) -> (String, SlideDirectives) {
+    let comment_regex = Regex::new("<!--((.|\n)*?)-->").expect("valid directive regex");
+    let mut spot_directives: Vec<(String, String)> = vec![];
+
+    let slide_markdown = comment_regex.replace_all(slide, |captures: &regex::Captures| {
+        let fields = match directive_fields(&captures[1]) {
+            Some(fields) if fields.iter().any(|(key, _)| is_directive_key(key)) => fields,
+            _ => return captures[0].to_owned(),
+        };
+
+        for (key, value) in fields {
+            match key.strip_prefix('_') {
+                Some(spot_key) => spot_directives.push((spot_key.to_owned(), value)),
+                None => {
+                    directives.apply(&key, &value);
+                }
+            }
+        }
+
+        String::new()
+    });
+
+    let mut slide_directives = directives.clone();
+    for (key, value) in spot_directives {
+        slide_directives.apply(&key, &value);
+    }
+
+    (slide_markdown.into_owned(), slide_directives)
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    escape_html(&mut escaped, text).unwrap_or_default();
+
+    escaped
+}
+
+fn render_slide(
+    slide_markdown: &str,
+    directives: &SlideDirectives,
+    theme: &str,
+    page: usize,
+    total_pages: usize,
+    trusted: bool,
+) -> String {
+    let slide_html = |markdown: &str| match trusted {
+        true => markdown_to_html(markdown),
+        false => sanitize_html(&markdown_to_html(markdown)),
+    };
+
+    let mut section_attrs = format!(r#"id="{page}" data-theme="{}""#, escape(theme));
+
+    if !directives.class.is_empty() {
+        section_attrs += &format!(r#" class="{}""#, escape(&directives.class));
+    }
+
+    let styles: Vec<String> = [
+        ("background-color", &directives.background_color),
+        ("color", &directives.color),
+    ]
+    .iter()
+    .filter(|(_, value)| !value.is_empty())
+    .map(|(property, value)| format!("{property}: {};", escape(value)))
+    .collect();
+
+    if !styles.is_empty() {
+        section_attrs += &format!(r#" style="{}""#, styles.join(" "));
+    }
+
+    if directives.paginate {
+        section_attrs += &format!(
+            r#" data-marpit-pagination="{page}" data-marpit-pagination-total="{total_pages}""#
+        );
+    }
+
+    let header = if directives.header.is_empty() {
+        String::new()
+    } else {
+        format!("<header>{}</header>", slide_html(&directives.header))
+    };
+
+    let footer = if directives.footer.is_empty() {
+        String::new()
+    } else {
+        format!("<footer>{}</footer>", slide_html(&directives.footer))
+    };
+
+    format!(
+        r#"<svg data-marpit-svg="" viewBox="0 0 {SLIDE_WIDTH} {SLIDE_HEIGHT}"><foreignObject width="{SLIDE_WIDTH}" height="{SLIDE_HEIGHT}"><section {section_attrs}>{header}{}{footer}</section></foreignObject></svg>"#,
+        slide_html(slide_markdown)
+    )
+}
+
+fn theme_css(theme: &str) -> String {
+    let read_css = |name: &str| {
+        fs::read_to_string(Path::new(MARP_THEMES_ROOT).join(format!("{name}.css")))
+            .map_err(|e| error!("read marp theme {name} failed: {e:?}"))
+            .ok()
+    };
+
+    let is_valid_name = !theme.is_empty()
+        && theme
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '-' || char == '_');
+
+    let theme_css = is_valid_name
+        .then(|| read_css(theme))
+        .flatten()
+        .or_else(|| read_css(DEFAULT_THEME))
+        .unwrap_or_default();
 
-    match parts.get(1) {
-        Some(header) => {
-            return header.trim().contains("marp: true");
+    [read_css("base").unwrap_or_default(), theme_css].join("\n")
+}
+
+pub fn marp_from_markdown_local(markdown: &str, trusted: bool) -> HbpResult<MarpContent> {
+    let (fields, body) = match parse_markdown_header(markdown)? {
+        Some(header) => (header.fields, &markdown[header.body_start..]),
+        None => (BTreeMap::new(), markdown),
+    };
+    let front_matter = &markdown[..markdown.len() - body.len()];
+
+    let mut directives = SlideDirectives::default();
+    let mut theme = DEFAULT_THEME.to_owned();
+
+    for (key, value) in front_matter.lines().filter_map(loose_field) {
+        match key.as_str() {
+            "theme" => theme = value,
+            key => {
+                directives.apply(key, &value);
+            }
         }
-        None => false,
     }
+
+    let style = match fields.get("style") {
+        Some(Value::String(style)) if trusted => style.to_owned(),
+        _ => String::new(),
+    };
+
+    let slides = split_slides(body);
+    let total_pages = slides.len();
+
+    let html = slides
+        .iter()
+        .enumerate()
+        .map(|(index, slide)| {
+            let (slide_markdown, slide_directives) = extract_directives(slide, &mut directives);
+
+            render_slide(
+                &slide_markdown,
+                &slide_directives,
+                &theme,
+                index + 1,
+                total_pages,
+                trusted,
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Ok(MarpContent {
+        html: format!(r#"<div class="marpit">{html}</div>"#),
+        css: [theme_css(&theme), style].join("\n"),
+    })
 }
 
-pub async fn marp_from_markdown(markdown: String) -> HbpResult<MarpContent> {
+async fn marp_from_api(markdown: String) -> HbpResult<MarpContent> {
     let url = format!("{}?json=1", from_env(EnvKey::MarpApiRoot));
     let form = multipart::Form::new().text("markdown", markdown);
 
@@ -32,8 +300,23 @@ pub async fn marp_from_markdown(markdown: String) -> HbpResult<MarpContent> {
     res.json::<MarpContent>().await.map_err(|e| e.into())
 }
 
-pub async fn render_marp(markdown: &str) -> HbpResult<String> {
-    let marp_content = marper::marp_from_markdown(markdown.to_owned()).await?;
+pub async fn marp_from_markdown(markdown: String, trusted: bool) -> HbpResult<MarpContent> {
+    // ? the remote service output can't be vetted, so untrusted decks always render here
+    if !trusted || from_env(EnvKey::MarpApiRoot).trim().is_empty() {
+        return marp_from_markdown_local(&markdown, trusted);
+    }
+
+    match marp_from_api(markdown.clone()).await {
+        Ok(marp_content) => Ok(marp_content),
+        Err(e) => {
+            error!("marp_from_api failed, rendering in-process: {e:?}");
+            marp_from_markdown_local(&markdown, trusted)
+        }
+    }
+}
+
+pub async fn render_marp(markdown: &str, trusted: bool) -> HbpResult<String> {
+    let marp_content = marper::marp_from_markdown(markdown.to_owned(), trusted).await?;
 
     let markdown_html = [
         marp_content.html,
@@ -56,3 +339,74 @@ pub async fn render_marp(markdown: &str) -> HbpResult<String> {
 
     Templater::new("markdown/markdown.html".into()).to_html(RenderData { markdown_html })
 }
+
+#[cfg(test)]
+mod marper_tests {
+    use super::{is_marp, marp_from_markdown_local, split_slides};
+
+    const DECK: &str = "---
+marp: true
+theme: gaia
+paginate: true
+footer: HBP
+---
+# Title
+
+<!-- _class: lead -->
+
+---
+
+<!-- backgroundColor: #123456 -->
+```
+---
+```
+
+---
+
+<!-- paginate: false -->
+# Last
+";
+
+    #[test]
+    fn detect_marp_header() {
+        assert!(is_marp(DECK));
+        assert!(!is_marp("---\ntitle: Not a deck\n---\n"));
+        assert!(!is_marp("# marp: true"));
+    }
+
+    #[test]
+    fn split_slides_outside_code_blocks() {
+        assert_eq!(split_slides("a\n---\nb\n```\n---\n```\n").len(), 2);
+    }
+
+    #[test]
+    fn render_slides_with_directives() {
+        let marp_content = marp_from_markdown_local(DECK, true).unwrap();
+        let sections: Vec<&str> = marp_content.html.split("<section ").skip(1).collect();
+
+        assert_eq!(sections.len(), 3);
+
+        assert!(sections[0].contains(r#"class="lead""#));
+        assert!(sections[0].contains(r#"data-marpit-pagination="1""#));
+        assert!(sections[0].contains("<footer><p>HBP</p>\n</footer>"));
+        assert!(!sections[0].contains("_class"));
+
+        assert!(!sections[1].contains("class="));
+        assert!(sections[1].contains("background-color: #123456;"));
+        assert!(sections[1].contains("<pre><code>---"));
+
+        assert!(sections[2].contains("background-color: #123456;"));
+        assert!(!sections[2].contains("data-marpit-pagination"));
+        assert!(sections[2].contains(r#"data-theme="gaia""#));
+    }
+
+    #[test]
+    fn sanitize_untrusted_slides() {
+        let deck = "---\nmarp: true\nstyle: \"</style><script>alert(1)</script>\"\n---\n# Hi <img src=x onerror=alert(2)>\n";
+        let marp_content = marp_from_markdown_local(deck, false).unwrap();
+
+        assert!(marp_content.html.contains("<h1"));
+        assert!(!marp_content.html.contains("onerror"));
+        assert!(!marp_content.css.contains("<script>"));
+    }
+}
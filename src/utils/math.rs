@@ -0,0 +1,336 @@
+use pulldown_cmark::escape::escape_html;
+use pulldown_cmark::{Event, Parser, Tag};
+use std::ops::Range;
+use std::path::Path;
+
+use super::fso::markdown_options;
+
+const PLACEHOLDER_START: char = '\u{F8F0}';
+const PLACEHOLDER_END: char = '\u{F8F1}';
+pub const STATIC_ROOT: &str = "static";
+
+// * Served from `/static`, see `static/vendor/README.md` for where to get them
+pub const KATEX_ASSETS: [&str; 2] = ["vendor/katex/katex.min.css", "vendor/katex/katex.min.js"];
+pub const MERMAID_ASSETS: [&str; 1] = ["vendor/mermaid/mermaid.min.js"];
+
+#[derive(Debug, PartialEq)]
+pub struct MathSpan {
+    pub tex: String,
+    pub display: bool,
+}
+
+fn code_ranges(markdown: &str) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    let mut code_block_depth = 0;
+
+    for (event, range) in Parser::new_ext(markdown, markdown_options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => {
+                if code_block_depth == 0 {
+                    ranges.push(range);
+                }
+                code_block_depth += 1;
+            }
+            Event::End(Tag::CodeBlock(_)) => code_block_depth -= 1,
+            Event::Code(_) | Event::Html(_) => ranges.push(range),
+            _ => {}
+        }
+    }
+
+    ranges
+}
+
+fn find_closing(markdown: &str, from: usize, delimiter: &str) -> Option<usize> {
+    let mut search_from = from;
+
+    while let Some(offset) = markdown[search_from..].find(delimiter) {
+        let index = search_from + offset;
+
+        if !markdown[..index].ends_with('\\') {
+            return Some(index);
+        }
+
+        search_from = index + delimiter.len();
+    }
+
+    None
+}
+
+fn math_at(markdown: &str, start: usize) -> Option<(Range<usize>, MathSpan)> {
+    let rest = &markdown[start..];
+
+    if rest.starts_with("$$") {
+        let tex_start = start + 2;
+        let tex_end = find_closing(markdown, tex_start, "$$")?;
+        let tex = markdown[tex_start..tex_end].trim();
+
+        if tex.is_empty() || tex.contains("\n\n") {
+            return None;
+        }
+
+        return Some((
+            start..tex_end + 2,
+            MathSpan {
+                tex: tex.to_owned(),
+                display: true,
+            },
+        ));
+    }
+
+    let tex_start = start + 1;
+    let first_char = markdown[tex_start..].chars().next()?;
+    if first_char.is_whitespace() || first_char == '$' {
+        return None;
+    }
+
+    let tex_end = find_closing(markdown, tex_start, "$")?;
+    let tex = &markdown[tex_start..tex_end];
+    let closes_after_digit = markdown[tex_end + 1..]
+        .chars()
+        .next()
+        .map(|char| char.is_ascii_digit())
+        .unwrap_or(false);
+
+    if tex.ends_with(char::is_whitespace) || tex.contains('\n') || closes_after_digit {
+        return None;
+    }
+
+    Some((
+        start..tex_end + 1,
+        MathSpan {
+            tex: tex.to_owned(),
+            display: false,
+        },
+    ))
+}
+
+pub fn extract_math(markdown: &str) -> (String, Vec<MathSpan>) {
+    if !markdown.contains('$') {
+        return (markdown.to_owned(), vec![]);
+    }
+
+    // ? Placeholders are private use chars, literal ones in the note would pass for math
+    let markdown = &markdown.replace([PLACEHOLDER_START, PLACEHOLDER_END], "");
+    let code_ranges = code_ranges(markdown);
+    let mut prepared = String::with_capacity(markdown.len());
+    let mut spans = vec![];
+    let mut index = 0;
+
+    while let Some(offset) = markdown[index..].find('$') {
+        let start = index + offset;
+        prepared.push_str(&markdown[index..start]);
+
+        let in_code = code_ranges.iter().any(|range| range.contains(&start));
+        let is_escaped = markdown[..start].ends_with('\\');
+
+        match (!in_code && !is_escaped)
+            .then(|| math_at(markdown, start))
+            .flatten()
+        {
+            Some((range, span)) => {
+                prepared.push_str(&format!(
+                    "{PLACEHOLDER_START}{}{PLACEHOLDER_END}",
+                    spans.len()
+                ));
+                spans.push(span);
+                index = range.end;
+            }
+            None => {
+                prepared.push('$');
+                index = start + 1;
+            }
+        }
+    }
+
+    prepared.push_str(&markdown[index..]);
+
+    (prepared, spans)
+}
+
+fn math_html(span: &MathSpan) -> String {
+    let mut tex = String::new();
+    let _ = escape_html(&mut tex, &span.tex);
+
+    if span.display {
+        format!("<div class=\"math math-display\">{tex}</div>\n")
+    } else {
+        format!(r#"<span class="math math-inline">{tex}</span>"#)
+    }
+}
+
+fn split_placeholders<'a>(text: &str, spans: &[MathSpan]) -> Vec<Event<'a>> {
+    let mut events = vec![];
+    let mut rest = text;
+
+    while let Some(start) = rest.find(PLACEHOLDER_START) {
+        let after_start = &rest[start + PLACEHOLDER_START.len_utf8()..];
+        let span = after_start.find(PLACEHOLDER_END).and_then(|end| {
+            let span = spans.get(after_start[..end].parse::<usize>().ok()?)?;
+            Some((span, end))
+        });
+
+        match span {
+            Some((span, end)) => {
+                if start > 0 {
+                    events.push(Event::Text(rest[..start].to_owned().into()));
+                }
+
+                events.push(Event::Html(math_html(span).into()));
+                rest = &after_start[end + PLACEHOLDER_END.len_utf8()..];
+            }
+            None => {
+                events.push(Event::Text(
+                    rest[..start + PLACEHOLDER_START.len_utf8()]
+                        .to_owned()
+                        .into(),
+                ));
+                rest = after_start;
+            }
+        }
+    }
+
+    if !rest.is_empty() {
+        events.push(Event::Text(rest.to_owned().into()));
+    }
+
+    events
+}
+
+pub fn render_math<'a>(events: Vec<Event<'a>>, spans: &[MathSpan]) -> Vec<Event<'a>> {
+    if spans.is_empty() {
+        return events;
+    }
+
+    let mut rendered: Vec<Event> = vec![];
+
+    for event in events {
+        match event {
+            Event::Text(text) if text.contains(PLACEHOLDER_START) => {
+                rendered.extend(split_placeholders(&text, spans))
+            }
+            Event::End(Tag::Paragraph) => {
+                let is_display_only = rendered.len() >= 2
+                    && matches!(rendered[rendered.len() - 2], Event::Start(Tag::Paragraph))
+                    && matches!(
+                        rendered.last(),
+                        Some(Event::Html(html)) if html.starts_with(r#"<div class="math"#)
+                    );
+
+                if is_display_only {
+                    let display = rendered.pop();
+                    rendered.pop();
+                    rendered.extend(display);
+                } else {
+                    rendered.push(Event::End(Tag::Paragraph));
+                }
+            }
+            event => rendered.push(event),
+        }
+    }
+
+    rendered
+}
+
+pub fn mermaid_html(code: &str) -> String {
+    let mut escaped = String::new();
+    let _ = escape_html(&mut escaped, code);
+
+    format!(r#"<pre class="mermaid">{escaped}</pre>"#)
+}
+
+// ? Vendored libraries are optional, pages without them keep showing the raw source
+pub fn is_vendored_in(static_root: &Path, assets: &[&str]) -> bool {
+    assets.iter().all(|asset| static_root.join(asset).is_file())
+}
+
+#[cfg(test)]
+mod math_tests {
+    use super::{extract_math, MathSpan, KATEX_ASSETS, MERMAID_ASSETS, STATIC_ROOT};
+    use crate::utils::fso::markdown_to_html;
+    use std::fs;
+    use std::path::Path;
+
+    fn span(tex: &str, display: bool) -> MathSpan {
+        MathSpan {
+            tex: tex.to_owned(),
+            display,
+        }
+    }
+
+    #[test]
+    fn extract_math_outside_code() {
+        let (_, spans) = extract_math(
+            "Inline $a_1 * b_2$ and\n\n$$\n\\frac{1}{2}\n$$\n\n`$x$` costs $5 and $6, \\$7",
+        );
+
+        assert_eq!(
+            spans,
+            vec![span("a_1 * b_2", false), span("\\frac{1}{2}", true)]
+        );
+        assert!(extract_math("```\n$x$\n```").1.is_empty());
+    }
+
+    #[test]
+    fn render_math_markup() {
+        assert_eq!(
+            markdown_to_html("Let $x_i < y_i$ hold\n\n$$\n\\sum_i x_i\n$$"),
+            "<p>Let <span class=\"math math-inline\">x_i &lt; y_i</span> hold</p>\n<div class=\"math math-display\">\\sum_i x_i</div>\n"
+        );
+    }
+
+    #[test]
+    fn ignore_literal_placeholders() {
+        assert_eq!(
+            markdown_to_html("Price $x$ \u{F8F0}9\u{F8F1} \u{F8F0}0\u{F8F1}"),
+            "<p>Price <span class=\"math math-inline\">x</span> 9 0</p>\n"
+        );
+        assert_eq!(
+            super::split_placeholders("a\u{F8F0}b", &[span("x", false)]).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn render_mermaid_blocks() {
+        assert_eq!(
+            markdown_to_html("```mermaid\ngraph TD; A-->B\n```"),
+            "<pre class=\"mermaid\">graph TD; A--&gt;B\n</pre>"
+        );
+    }
+
+    #[test]
+    fn referenced_assets_exist() {
+        let template = fs::read_to_string("template/markdown/markdown.html").unwrap();
+        let section_of = |name: &str| {
+            let start = template.find(&format!("{{{{#{name}}}}}")).unwrap();
+            let end = template.find(&format!("{{{{/{name}}}}}")).unwrap();
+
+            template[start..end].to_owned()
+        };
+        let assets = template
+            .split("\"/static/")
+            .skip(1)
+            .filter_map(|rest| rest.split('"').next())
+            .collect::<Vec<_>>();
+
+        assert!(!assets.is_empty());
+
+        for asset in assets {
+            // * Vendored ones are only emitted behind the section checking they are on disk
+            let section = if KATEX_ASSETS.contains(&asset) {
+                Some("has_math")
+            } else if MERMAID_ASSETS.contains(&asset) {
+                Some("has_mermaid")
+            } else {
+                None
+            };
+
+            match section {
+                Some(section) => assert!(section_of(section).contains(asset), "{asset}"),
+                // ? `css/highlight.css` is generated by its own route
+                None if asset == "css/highlight.css" => {}
+                None => assert!(Path::new(STATIC_ROOT).join(asset).is_file(), "{asset}"),
+            }
+        }
+    }
+}
@@ -3,20 +3,33 @@ use std::path::Path;
 use httpstatus::StatusCode;
 use image::ImageFormat;
 use rocket::http::Status;
-use tempfile::NamedTempFile;
-
-use self::responders::HbpResult;
 
 pub mod auth;
 pub mod constants;
 pub mod cors;
+pub mod downloads;
 pub mod env;
+pub mod export;
 pub mod fso;
 pub mod guards;
+pub mod images;
 pub mod marper;
+pub mod math;
+pub mod pagination;
+pub mod paths;
+pub mod plaintext;
+pub mod previews;
+pub mod reading;
+pub mod render_cache;
 pub mod responders;
+pub mod sanitize;
+pub mod search;
 pub mod setup_logger;
+pub mod syntax;
 pub mod template;
+pub mod thumbnails;
+pub mod toc;
+pub mod wiki;
 
 pub fn timestamp_now() -> i64 {
     chrono::Utc::now()
@@ -30,18 +43,14 @@ pub fn status_from(status_code: StatusCode) -> Status {
         .unwrap_or_else(|| panic!("status_code {} is NOT valid", status_code.as_u16()))
 }
 
-pub fn create_thumbnail(path: &Path) -> HbpResult<NamedTempFile> {
-    let suffix = ImageFormat::Png.extensions_str().first().unwrap_or(&"png");
-
-    let mut thumbnail = tempfile::Builder::new()
-        .suffix(&format!(".{suffix}"))
-        .tempfile()?;
-
-    image::open(path)?
-        .thumbnail(512, 512)
-        .write_to(&mut thumbnail, ImageFormat::Png)?;
+pub fn is_readable_image(path: &Path) -> bool {
+    ImageFormat::from_path(path)
+        .map(|format| format.can_read())
+        .unwrap_or(false)
+}
 
-    Ok(thumbnail)
+pub fn has_preview(path: &Path) -> bool {
+    is_readable_image(path) || previews::has_rendered_preview(path)
 }
 
 pub fn url_encode_path(path: &str) -> String {
@@ -0,0 +1,119 @@
+use serde::Serialize;
+
+use crate::shared::ApiPage;
+
+#[derive(Clone, Copy, Debug)]
+pub struct PageSize {
+    pub default: usize,
+    pub max: usize,
+}
+
+pub const MARKDOWN_PAGE_SIZE: PageSize = PageSize {
+    default: 10,
+    max: 100,
+};
+
+#[derive(Serialize, Debug)]
+pub struct Pagination {
+    pub page: usize,
+    pub total_pages: usize,
+    pub prev_url: Option<String>,
+    pub next_url: Option<String>,
+}
+
+impl Pagination {
+    pub fn of(page: &ApiPage, base_url: &str) -> Pagination {
+        let page_url = |index: usize| format!("{base_url}?page={index}&per_page={}", page.per_page);
+
+        Pagination {
+            page: page.page,
+            total_pages: page.total_pages,
+            prev_url: (page.page > 1).then(|| page_url(page.page - 1)),
+            next_url: (page.page < page.total_pages).then(|| page_url(page.page + 1)),
+        }
+    }
+}
+
+// * Out of range pages & sizes are clamped, never rejected
+pub fn page_of<T>(
+    items: Vec<T>,
+    page: Option<usize>,
+    per_page: Option<usize>,
+    page_size: PageSize,
+) -> (Vec<T>, ApiPage) {
+    let per_page = per_page
+        .unwrap_or(page_size.default)
+        .clamp(1, page_size.max);
+    let total_items = items.len();
+    let total_pages = total_items.div_ceil(per_page).max(1);
+    let page = page.unwrap_or(1).clamp(1, total_pages);
+
+    let items = items
+        .into_iter()
+        .skip((page - 1) * per_page)
+        .take(per_page)
+        .collect();
+
+    (
+        items,
+        ApiPage {
+            page,
+            per_page,
+            total_pages,
+            total_items,
+        },
+    )
+}
+
+pub fn paginate<T>(
+    items: Vec<T>,
+    page: Option<usize>,
+    per_page: Option<usize>,
+    base_url: &str,
+) -> (Vec<T>, Pagination) {
+    let (items, page) = page_of(items, page, per_page, MARKDOWN_PAGE_SIZE);
+
+    (items, Pagination::of(&page, base_url))
+}
+
+#[cfg(test)]
+mod pagination_tests {
+    use super::{page_of, paginate, PageSize};
+
+    const PAGE_SIZE: PageSize = PageSize {
+        default: 100,
+        max: 1000,
+    };
+
+    #[test]
+    fn clamp_pages() {
+        let (items, page) = page_of((1..=25).collect(), Some(3), Some(10), PAGE_SIZE);
+        assert_eq!(items, (21..=25).collect::<Vec<_>>());
+        assert_eq!((page.page, page.total_pages, page.total_items), (3, 3, 25));
+
+        let (items, page) = page_of((1..=5).collect::<Vec<_>>(), Some(9), Some(0), PAGE_SIZE);
+        assert_eq!(items, [5]);
+        assert_eq!((page.page, page.per_page), (5, 1));
+
+        let (items, page) = page_of(Vec::<u8>::new(), None, None, PAGE_SIZE);
+        assert!(items.is_empty());
+        assert_eq!((page.page, page.total_pages), (1, 1));
+    }
+
+    #[test]
+    fn link_neighbour_pages() {
+        let (items, pagination) = paginate((1..=25).collect::<Vec<_>>(), Some(2), None, "/blogs");
+        assert_eq!(items, (11..=20).collect::<Vec<_>>());
+        assert_eq!(
+            pagination.prev_url.as_deref(),
+            Some("/blogs?page=1&per_page=10")
+        );
+        assert_eq!(
+            pagination.next_url.as_deref(),
+            Some("/blogs?page=3&per_page=10")
+        );
+
+        let (_, pagination) = paginate(vec![1], None, None, "/blogs");
+        assert!(pagination.prev_url.is_none() && pagination.next_url.is_none());
+    }
+}
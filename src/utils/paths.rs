@@ -0,0 +1,212 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::shared::interfaces::ApiError;
+
+use super::responders::HbpResult;
+
+fn escape_error(sub_path: &Path) -> ApiError {
+    ApiError::forbidden().append_error(format!("{sub_path:?} escapes its root"))
+}
+
+// * Lexical pass: only plain names survive, `..`, `/` & prefixes are rejected outright
+fn normalized_of(sub_path: &Path) -> HbpResult<PathBuf> {
+    let mut normalized = PathBuf::new();
+
+    for component in sub_path.components() {
+        match component {
+            Component::Normal(component) => normalized.push(component),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(ApiError::bad_request(vec![format!(
+                    "{sub_path:?} must be a relative path without `..`"
+                )])
+                .into())
+            }
+        }
+    }
+
+    Ok(normalized)
+}
+
+// * Symlinks in the existing part are followed, the result is `root` + the canonical relative path
+pub fn resolve_in(root: &Path, sub_path: &Path) -> HbpResult<PathBuf> {
+    let normalized = normalized_of(sub_path)?;
+    let canonical_root = root.canonicalize()?;
+
+    let mut resolved = canonical_root.clone();
+    let mut missing = PathBuf::new();
+
+    for component in normalized.components() {
+        if !missing.as_os_str().is_empty() {
+            missing.push(component);
+            continue;
+        }
+
+        let candidate = resolved.join(component);
+
+        if fs::symlink_metadata(&candidate).is_err() {
+            missing.push(component);
+            continue;
+        }
+
+        // ? A dangling symlink can NOT be checked, writing through it could land anywhere
+        let canonical = candidate
+            .canonicalize()
+            .map_err(|_| escape_error(sub_path))?;
+
+        if !canonical.starts_with(&canonical_root) {
+            return Err(escape_error(sub_path).into());
+        }
+
+        resolved = canonical;
+    }
+
+    let relative = resolved
+        .strip_prefix(&canonical_root)
+        .map_err(|_| escape_error(sub_path))?;
+
+    // ? `join("")` appends a trailing slash, which turns existing files into missing ones
+    Ok(root
+        .components()
+        .chain(relative.components())
+        .chain(missing.components())
+        .collect())
+}
+
+pub fn relative_path_in(root: &Path, path: &Path) -> Option<String> {
+    path.strip_prefix(root)
+        .ok()
+        .map(|relative| relative.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod paths_tests {
+    use super::{relative_path_in, resolve_in};
+    use httpstatus::StatusCode;
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use std::path::Path;
+
+    fn status_of(root: &Path, sub_path: &str) -> StatusCode {
+        resolve_in(root, Path::new(sub_path))
+            .err()
+            .map(|e| e.api_error.status_code)
+            .unwrap_or(StatusCode::Ok)
+    }
+
+    #[test]
+    fn resolve_plain_paths() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("public/a")).unwrap();
+
+        assert_eq!(
+            resolve_in(root.path(), Path::new("public/./a")).unwrap(),
+            root.path().join("public/a")
+        );
+        assert_eq!(
+            resolve_in(root.path(), Path::new("public/new/file.txt")).unwrap(),
+            root.path().join("public/new/file.txt")
+        );
+        assert_eq!(resolve_in(root.path(), Path::new("")).unwrap(), root.path());
+        assert!(resolve_in(root.path(), Path::new("public/a"))
+            .unwrap()
+            .as_os_str()
+            .to_string_lossy()
+            .ends_with("/public/a"));
+        assert_eq!(
+            resolve_in(root.path(), Path::new("%2e%2e/..%2f")).unwrap(),
+            root.path().join("%2e%2e/..%2f")
+        );
+    }
+
+    #[test]
+    fn reject_traversal() {
+        let root = tempfile::tempdir().unwrap();
+
+        for hostile in [
+            "..",
+            "../etc/passwd",
+            "public/../../etc",
+            "public/..",
+            "/etc/passwd",
+            "//etc",
+            "a/b/../../../x",
+        ] {
+            assert_eq!(
+                status_of(root.path(), hostile),
+                StatusCode::BadRequest,
+                "{hostile}"
+            );
+        }
+    }
+
+    #[test]
+    fn reject_symlink_escapes() {
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("public")).unwrap();
+        symlink(outside.path(), root.path().join("public/out")).unwrap();
+        symlink(
+            outside.path().join("secret.txt"),
+            root.path().join("public/secret.txt"),
+        )
+        .unwrap();
+        symlink("/nowhere/at/all", root.path().join("public/dangling")).unwrap();
+        symlink("..", root.path().join("public/up")).unwrap();
+        symlink("../..", root.path().join("public/way-up")).unwrap();
+
+        for hostile in [
+            "public/out",
+            "public/out/secret.txt",
+            "public/out/new/file.txt",
+            "public/secret.txt",
+            "public/dangling",
+            "public/dangling/file.txt",
+            "public/way-up",
+            "public/up/public/way-up/etc",
+        ] {
+            assert_eq!(
+                status_of(root.path(), hostile),
+                StatusCode::Forbidden,
+                "{hostile}"
+            );
+        }
+    }
+
+    #[test]
+    fn follow_symlinks_inside_root() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("private/photos")).unwrap();
+        fs::create_dir_all(root.path().join("public")).unwrap();
+        symlink("../private/photos", root.path().join("public/photos")).unwrap();
+        symlink("..", root.path().join("public/up")).unwrap();
+
+        // * The real location is returned, so access checks apply to the target
+        assert_eq!(
+            resolve_in(root.path(), Path::new("public/photos/a.jpg")).unwrap(),
+            root.path().join("private/photos/a.jpg")
+        );
+        assert_eq!(
+            resolve_in(root.path(), Path::new("public/up/private")).unwrap(),
+            root.path().join("private")
+        );
+    }
+
+    #[test]
+    fn relative_paths() {
+        let root = Path::new("/srv/files");
+
+        assert_eq!(
+            relative_path_in(root, Path::new("/srv/files/public/a.txt")).as_deref(),
+            Some("public/a.txt")
+        );
+        assert_eq!(
+            relative_path_in(root, Path::new("/srv/files-other/a")),
+            None
+        );
+        assert_eq!(relative_path_in(root, root).as_deref(), Some(""));
+    }
+}
@@ -0,0 +1,65 @@
+use chardetng::EncodingDetector;
+use encoding_rs::Encoding;
+use serde::Serialize;
+use std::path::Path;
+
+use super::responders::HbpResult;
+use super::template::{IndexLayout, Templater};
+
+pub fn decode_text(bytes: &[u8]) -> String {
+    let encoding = match Encoding::for_bom(bytes) {
+        Some((encoding, _)) => encoding,
+        None => {
+            let mut detector = EncodingDetector::new();
+            detector.feed(bytes, true);
+
+            detector.guess(None, true)
+        }
+    };
+
+    // ? `decode` strips the BOM and replaces malformed sequences
+    let (text, _, _) = encoding.decode(bytes);
+
+    text.into_owned()
+}
+
+#[derive(Serialize, Debug)]
+struct PlaintextTemplate {
+    content: String,
+}
+
+pub fn render_plaintext(file_path: &Path, layout_data: IndexLayout) -> HbpResult<String> {
+    let content = decode_text(&std::fs::read(file_path)?);
+
+    Templater::new("markdown/plaintext.html".into())
+        .to_html_page(PlaintextTemplate { content }, layout_data)
+}
+
+#[cfg(test)]
+mod plaintext_tests {
+    use super::{decode_text, PlaintextTemplate};
+    use crate::utils::template::Templater;
+
+    #[test]
+    fn decode_detected_encodings() {
+        assert_eq!(decode_text("xin chào".as_bytes()), "xin chào");
+        assert_eq!(decode_text(b"\xEF\xBB\xBFbom"), "bom");
+        assert_eq!(decode_text(b"\xFF\xFEh\x00i\x00"), "hi");
+        assert_eq!(
+            decode_text(b"Les \xE9l\xE8ves \xE9taient tr\xE8s \xE9nerv\xE9s"),
+            "Les élèves étaient très énervés"
+        );
+    }
+
+    #[test]
+    fn escape_and_preserve_whitespace() {
+        let html = Templater::new("markdown/plaintext.html".into())
+            .to_html(PlaintextTemplate {
+                content: "<script>alert(1)</script>\n  two  spaces\n\tand a tab".to_owned(),
+            })
+            .unwrap();
+
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;\n  two  spaces\n\tand a tab"));
+    }
+}
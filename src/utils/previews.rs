@@ -0,0 +1,419 @@
+use image::DynamicImage;
+use log::{error, info};
+use mime_guess::Mime;
+use pulldown_cmark::escape::escape_html;
+use std::env;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::plaintext::decode_text;
+use super::responders::HbpResult;
+use super::thumbnails::{
+    cached_thumbnail_of, has_failed, mark_failed, thumbnail_cache_root, thumbnail_of,
+    ThumbnailFormat, STAGING_PREFIX,
+};
+use crate::shared::interfaces::ApiError;
+
+const EXTRACTOR_TIMEOUT: Duration = Duration::from_secs(30);
+const EXTRACTED_FRAME: &str = "frame.png";
+const SNIPPET_BYTES: u64 = 8 * 1024;
+const SNIPPET_LINES: usize = 16;
+const SNIPPET_COLUMNS: usize = 64;
+
+pub enum Preview {
+    Thumbnail(PathBuf),
+    Svg(String),
+}
+
+// * Extractors render a single frame of `source` into a PNG at `frame`, the first installed one wins
+pub struct FrameExtractor {
+    pub program: &'static str,
+    pub mime: &'static str,
+    args: fn(&Path, &Path) -> Vec<OsString>,
+}
+
+fn ffmpegthumbnailer_args(source: &Path, frame: &Path) -> Vec<OsString> {
+    vec![
+        "-i".into(),
+        source.into(),
+        "-o".into(),
+        frame.into(),
+        "-s".into(),
+        "0".into(),
+        "-t".into(),
+        "10%".into(),
+    ]
+}
+
+fn ffmpeg_args(source: &Path, frame: &Path) -> Vec<OsString> {
+    vec![
+        "-nostdin".into(),
+        "-v".into(),
+        "error".into(),
+        "-ss".into(),
+        "1".into(),
+        "-i".into(),
+        source.into(),
+        "-frames:v".into(),
+        "1".into(),
+        "-y".into(),
+        frame.into(),
+    ]
+}
+
+fn pdftoppm_args(source: &Path, frame: &Path) -> Vec<OsString> {
+    vec![
+        "-png".into(),
+        "-f".into(),
+        "1".into(),
+        "-l".into(),
+        "1".into(),
+        "-singlefile".into(),
+        "-scale-to".into(),
+        "1600".into(),
+        source.into(),
+        // ? `pdftoppm` takes an output root & appends `.png` itself
+        frame.with_extension("").into(),
+    ]
+}
+
+fn mutool_args(source: &Path, frame: &Path) -> Vec<OsString> {
+    vec![
+        "draw".into(),
+        "-q".into(),
+        "-o".into(),
+        frame.into(),
+        "-F".into(),
+        "png".into(),
+        source.into(),
+        "1".into(),
+    ]
+}
+
+pub const FRAME_EXTRACTORS: [FrameExtractor; 4] = [
+    FrameExtractor {
+        program: "ffmpegthumbnailer",
+        mime: "video",
+        args: ffmpegthumbnailer_args,
+    },
+    FrameExtractor {
+        program: "ffmpeg",
+        mime: "video",
+        args: ffmpeg_args,
+    },
+    FrameExtractor {
+        program: "pdftoppm",
+        mime: "application/pdf",
+        args: pdftoppm_args,
+    },
+    FrameExtractor {
+        program: "mutool",
+        mime: "application/pdf",
+        args: mutool_args,
+    },
+];
+
+impl FrameExtractor {
+    fn matches(&self, mime: &Mime) -> bool {
+        mime.essence_str() == self.mime || mime.type_() == self.mime
+    }
+
+    fn is_installed(&self) -> bool {
+        env::var_os("PATH")
+            .map(|paths| env::split_paths(&paths).any(|dir| dir.join(self.program).is_file()))
+            .unwrap_or(false)
+    }
+
+    fn extract(&self, source: &Path, frame: &Path) -> HbpResult<DynamicImage> {
+        let mut child = Command::new(self.program)
+            .args((self.args)(source, frame))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let started_at = Instant::now();
+
+        // ? A corrupted file may hang the tool, it must NOT hold a worker forever
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+
+            if started_at.elapsed() > EXTRACTOR_TIMEOUT {
+                child.kill()?;
+                child.wait()?;
+
+                return Err(ApiError::internal_server_error()
+                    .append_error(format!("{} timed out", self.program))
+                    .into());
+            }
+
+            thread::sleep(Duration::from_millis(50));
+        };
+
+        if !status.success() || !frame.is_file() {
+            return Err(ApiError::unprocessable_entity()
+                .append_error(format!("{} failed with {status}", self.program))
+                .into());
+        }
+
+        Ok(image::open(frame)?)
+    }
+}
+
+fn mime_of(path: &Path) -> Option<Mime> {
+    mime_guess::from_path(path).first()
+}
+
+// * `$PATH` is scanned once, listings ask for every file
+pub fn installed_frame_extractors() -> &'static [&'static FrameExtractor] {
+    static INSTALLED: OnceLock<Vec<&'static FrameExtractor>> = OnceLock::new();
+
+    INSTALLED.get_or_init(|| {
+        let installed = FRAME_EXTRACTORS
+            .iter()
+            .filter(|extractor| extractor.is_installed())
+            .collect::<Vec<_>>();
+
+        info!(
+            "installed_frame_extractors() found {:?}",
+            installed
+                .iter()
+                .map(|extractor| extractor.program)
+                .collect::<Vec<_>>()
+        );
+
+        installed
+    })
+}
+
+fn frame_extractors_of(mime: &Mime) -> impl Iterator<Item = &'static FrameExtractor> + '_ {
+    installed_frame_extractors()
+        .iter()
+        .copied()
+        .filter(|extractor| extractor.matches(mime))
+}
+
+fn is_text(mime: &Mime) -> bool {
+    mime.type_() == mime_guess::mime::TEXT
+}
+
+pub fn has_rendered_preview(path: &Path) -> bool {
+    mime_of(path)
+        .map(|mime| is_text(&mime) || frame_extractors_of(&mime).next().is_some())
+        .unwrap_or(false)
+}
+
+fn frame_thumbnail_of(
+    path: &Path,
+    width: u32,
+    format: ThumbnailFormat,
+    extractor: &FrameExtractor,
+) -> HbpResult<PathBuf> {
+    cached_thumbnail_of(
+        &thumbnail_cache_root(),
+        path,
+        width,
+        format,
+        |path, cache_dir| {
+            let staging_dir = tempfile::Builder::new()
+                .prefix(STAGING_PREFIX)
+                .tempdir_in(cache_dir)?;
+
+            extractor.extract(path, &staging_dir.path().join(EXTRACTED_FRAME))
+        },
+    )
+}
+
+fn escaped(text: &str) -> String {
+    let mut escaped = String::new();
+    let _ = escape_html(&mut escaped, text);
+
+    escaped
+}
+
+fn label_of(path: &Path) -> String {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_uppercase())
+        .unwrap_or_else(|| "FILE".to_owned())
+        .chars()
+        .take(4)
+        .collect()
+}
+
+fn icon_color_of(mime: Option<&Mime>) -> &'static str {
+    match mime.map(|mime| (mime.type_().as_str(), mime.subtype().as_str())) {
+        Some(("image", _)) => "#43a047",
+        Some(("video", _)) => "#e53935",
+        Some(("audio", _)) => "#8e24aa",
+        Some(("text", _)) => "#1e88e5",
+        Some(("application", "pdf")) => "#c62828",
+        Some(("application", "zip" | "gzip" | "x-tar" | "x-7z-compressed" | "vnd.rar")) => {
+            "#f9a825"
+        }
+        _ => "#757575",
+    }
+}
+
+pub fn icon_svg_of(path: &Path, width: u32) -> String {
+    let mime = mime_of(path);
+
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{width}" viewBox="0 0 96 96"><path d="M20 6h40l20 20v64H20z" fill="{}"/><path d="M60 6v20h20z" fill="#fff" fill-opacity=".4"/><text x="50" y="70" font-family="sans-serif" font-size="16" font-weight="bold" fill="#fff" text-anchor="middle">{}</text></svg>"##,
+        icon_color_of(mime.as_ref()),
+        escaped(&label_of(path))
+    )
+}
+
+fn snippet_lines_of(text: &str) -> Vec<String> {
+    text.lines()
+        .take(SNIPPET_LINES)
+        .map(|line| {
+            line.replace('\t', "    ")
+                .chars()
+                .filter(|char| !char.is_control())
+                .take(SNIPPET_COLUMNS)
+                .collect()
+        })
+        .collect()
+}
+
+pub fn snippet_svg_of(path: &Path, width: u32) -> HbpResult<String> {
+    let mut bytes = vec![];
+    File::open(path)?
+        .take(SNIPPET_BYTES)
+        .read_to_end(&mut bytes)?;
+
+    let title = path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let lines = snippet_lines_of(&decode_text(&bytes))
+        .iter()
+        .enumerate()
+        .map(|(index, line)| {
+            format!(
+                r#"<text x="16" y="{}">{}</text>"#,
+                64 + index * 18,
+                escaped(line)
+            )
+        })
+        .collect::<String>();
+
+    Ok(format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{}" viewBox="0 0 480 360"><rect width="480" height="360" rx="8" fill="#fafafa" stroke="#e0e0e0"/><rect width="480" height="40" rx="8" fill="{}"/><text x="16" y="26" font-family="sans-serif" font-size="16" font-weight="bold" fill="#fff">{}</text><g font-family="monospace" font-size="13" fill="#424242" xml:space="preserve">{lines}</g></svg>"##,
+        width * 3 / 4,
+        icon_color_of(mime_of(path).as_ref()),
+        escaped(&title)
+    ))
+}
+
+// * Images, then extracted frames, then text snippets, and a per mime icon when all else fails
+pub fn preview_of(path: &Path, width: u32, format: ThumbnailFormat) -> HbpResult<Preview> {
+    // ? Checked upfront, an unsupported format must NOT count as a failed extraction
+    format.assert_encodable()?;
+
+    if super::is_readable_image(path) {
+        return Ok(Preview::Thumbnail(thumbnail_of(path, width, format)?));
+    }
+
+    let mime = mime_of(path);
+
+    if let Some(mime) = &mime {
+        let mut extractors = frame_extractors_of(mime).peekable();
+
+        // ? A corrupted file fails every time, possibly after a timeout, so it is tried once per version
+        if extractors.peek().is_some() && !has_failed(&thumbnail_cache_root(), path) {
+            for extractor in extractors {
+                match frame_thumbnail_of(path, width, format, extractor) {
+                    Ok(thumbnail) => return Ok(Preview::Thumbnail(thumbnail)),
+                    Err(e) => error!("{} failed for {path:?}: {e:?}", extractor.program),
+                }
+            }
+
+            if let Err(e) = mark_failed(&thumbnail_cache_root(), path) {
+                error!("mark_failed() failed for {path:?}: {e:?}");
+            }
+        }
+
+        if is_text(mime) {
+            return Ok(Preview::Svg(snippet_svg_of(path, width)?));
+        }
+    }
+
+    Ok(Preview::Svg(icon_svg_of(path, width)))
+}
+
+pub fn warm_up_previews(paths: Vec<PathBuf>, widths: Vec<u32>, format: Option<ThumbnailFormat>) {
+    let mut warmed = 0;
+
+    for path in &paths {
+        let format = format.unwrap_or_else(|| ThumbnailFormat::of_source(path));
+
+        for width in &widths {
+            match preview_of(path, *width, format) {
+                Ok(_) => warmed += 1,
+                Err(e) => error!("warm_up_previews() failed for {path:?}@{width}: {e:?}"),
+            }
+        }
+    }
+
+    info!(
+        "warm_up_previews() cached {warmed} previews for {} files",
+        paths.len()
+    );
+}
+
+#[cfg(test)]
+mod previews_tests {
+    use super::{icon_svg_of, preview_of, snippet_lines_of, snippet_svg_of, Preview};
+    use crate::utils::thumbnails::ThumbnailFormat;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn icons_per_mime() {
+        let video_icon = icon_svg_of(Path::new("a/clip.mp4"), 96);
+        assert!(video_icon.contains("#e53935"));
+        assert!(video_icon.contains(">MP4<"));
+
+        let unknown_icon = icon_svg_of(Path::new("a/Makefile"), 96);
+        assert!(unknown_icon.contains("#757575"));
+        assert!(unknown_icon.contains(">FILE<"));
+    }
+
+    #[test]
+    fn snippet_of_text() {
+        let lines = snippet_lines_of(&format!("\ta\u{7}b\n{}\n", "x".repeat(100)));
+        assert_eq!(lines, ["    ab".to_owned(), "x".repeat(64)]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("<notes>.md");
+        fs::write(&path, "# Hi <script>alert(1)</script>\n\n& more").unwrap();
+
+        let svg = snippet_svg_of(&path, 320).unwrap();
+        assert!(svg.contains(r#"width="320" height="240""#));
+        assert!(svg.contains("&lt;notes&gt;.md"));
+        assert!(svg.contains("# Hi &lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(svg.contains("&amp; more"));
+        assert!(!svg.contains("<script>"));
+    }
+
+    #[test]
+    fn fall_back_to_icons() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("archive.zip");
+        fs::write(&path, "PK").unwrap();
+
+        match preview_of(&path, 320, ThumbnailFormat::Jpeg).unwrap() {
+            Preview::Svg(svg) => assert!(svg.contains(">ZIP<")),
+            Preview::Thumbnail(_) => panic!("archives have no thumbnail"),
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use pulldown_cmark::{Event, Parser, Tag};
+
+use super::fso::markdown_options;
+
+pub const WORDS_PER_MINUTE: usize = 200;
+pub const EXCERPT_MAX_CHARS: usize = 200;
+
+fn is_cjk(char: char) -> bool {
+    matches!(
+        char,
+        '\u{3040}'..='\u{30ff}' | '\u{3400}'..='\u{4dbf}' | '\u{4e00}'..='\u{9fff}' | '\u{ac00}'..='\u{d7af}'
+    )
+}
+
+fn words_in(text: &str) -> usize {
+    text.split_whitespace()
+        .map(|word| {
+            let cjk_chars = word.chars().filter(|char| is_cjk(*char)).count();
+            let has_other_chars = word
+                .chars()
+                .any(|char| !is_cjk(char) && char.is_alphanumeric());
+
+            cjk_chars + usize::from(has_other_chars)
+        })
+        .sum()
+}
+
+pub fn word_count(markdown: &str) -> usize {
+    let mut in_code_block = false;
+
+    Parser::new_ext(markdown, markdown_options())
+        .map(|event| match event {
+            Event::Start(Tag::CodeBlock(_)) => {
+                in_code_block = true;
+                0
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                in_code_block = false;
+                0
+            }
+            Event::Text(text) | Event::Code(text) if !in_code_block => words_in(&text),
+            _ => 0,
+        })
+        .sum()
+}
+
+pub fn reading_minutes(word_count: usize) -> usize {
+    word_count.div_ceil(WORDS_PER_MINUTE).max(1)
+}
+
+fn truncate_words(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_owned();
+    }
+
+    let cut_at = text
+        .char_indices()
+        .nth(max_chars)
+        .map(|(index, _)| index)
+        .unwrap_or(text.len());
+    let truncated = &text[..cut_at];
+    let truncated = match truncated.rfind(char::is_whitespace) {
+        Some(index) if index > 0 => &truncated[..index],
+        _ => truncated,
+    };
+
+    format!(
+        "{}…",
+        truncated.trim_end_matches(|char: char| char.is_ascii_punctuation())
+    )
+}
+
+pub fn excerpt_of(markdown: &str) -> String {
+    let mut excerpt = String::new();
+    let mut in_paragraph = false;
+    let mut skip_depth = 0;
+
+    for event in Parser::new_ext(markdown, markdown_options()) {
+        match event {
+            Event::Start(Tag::Paragraph) if skip_depth == 0 => in_paragraph = true,
+            Event::End(Tag::Paragraph) if in_paragraph => {
+                if !excerpt.trim().is_empty() {
+                    break;
+                }
+
+                in_paragraph = false;
+                excerpt.clear();
+            }
+            Event::Start(
+                Tag::BlockQuote
+                | Tag::List(_)
+                | Tag::Table(_)
+                | Tag::FootnoteDefinition(_)
+                | Tag::Image(..),
+            ) => skip_depth += 1,
+            Event::End(
+                Tag::BlockQuote
+                | Tag::List(_)
+                | Tag::Table(_)
+                | Tag::FootnoteDefinition(_)
+                | Tag::Image(..),
+            ) => skip_depth -= 1,
+            Event::Text(text) | Event::Code(text) if in_paragraph && skip_depth == 0 => {
+                excerpt.push_str(&text)
+            }
+            Event::SoftBreak | Event::HardBreak if in_paragraph && skip_depth == 0 => {
+                excerpt.push(' ')
+            }
+            _ => {}
+        }
+    }
+
+    let excerpt = excerpt.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    truncate_words(&excerpt, EXCERPT_MAX_CHARS)
+}
+
+#[cfg(test)]
+mod reading_tests {
+    use super::{excerpt_of, reading_minutes, word_count};
+
+    #[test]
+    fn count_words() {
+        assert_eq!(
+            word_count("# Hello world\n\nOne, two `three` — 4!\n\n```\nlet x;\n```"),
+            6
+        );
+        assert_eq!(word_count("Xin chào các bạn"), 4);
+        assert_eq!(word_count("日本語 and more"), 5);
+        assert_eq!(word_count(""), 0);
+    }
+
+    #[test]
+    fn estimate_reading_minutes() {
+        assert_eq!(reading_minutes(0), 1);
+        assert_eq!(reading_minutes(200), 1);
+        assert_eq!(reading_minutes(201), 2);
+    }
+
+    #[test]
+    fn excerpt_from_first_paragraph() {
+        assert_eq!(
+            excerpt_of(
+                "# Title\n\n> quoted\n\n![img](x.png)\n\nFirst *real*\nparagraph.\n\nSecond."
+            ),
+            "First real paragraph."
+        );
+
+        let long = "word ".repeat(60);
+        let excerpt = excerpt_of(&long);
+        assert!(excerpt.ends_with("word…"));
+        assert!(excerpt.chars().count() <= 201);
+
+        assert_eq!(excerpt_of("# Only a heading"), "");
+    }
+}
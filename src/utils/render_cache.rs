@@ -0,0 +1,262 @@
+use log::error;
+use lru::LruCache;
+use sled::Db;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::data::render_cache_orm::RenderCacheOrm;
+
+use super::guards::headers::CacheValidators;
+use super::search::IndexVersion;
+
+pub const RENDER_CACHE_SIZE: usize = 256;
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum RenderKind {
+    Markdown,
+    Marp,
+}
+
+impl RenderKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RenderKind::Markdown => "markdown",
+            RenderKind::Marp => "marp",
+        }
+    }
+}
+
+#[derive(Hash, PartialEq, Eq)]
+struct RenderKey {
+    kind: RenderKind,
+    path: PathBuf,
+    mtime: u128,
+}
+
+fn modified_of(path: &Path) -> Option<SystemTime> {
+    path.metadata()
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn nanos_of(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default()
+}
+
+pub struct RenderCache {
+    entries: Mutex<LruCache<RenderKey, String>>,
+    persist: bool,
+}
+
+impl RenderCache {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+
+        Self {
+            entries: Mutex::new(LruCache::new(capacity)),
+            persist: false,
+        }
+    }
+
+    pub fn persist(mut self, persist: bool) -> Self {
+        self.persist = persist;
+
+        self
+    }
+
+    fn key_of(path: &Path, kind: RenderKind) -> Option<RenderKey> {
+        Some(RenderKey {
+            kind,
+            path: path.to_owned(),
+            mtime: nanos_of(modified_of(path)?),
+        })
+    }
+
+    pub async fn get(&self, db: &Db, path: &Path, kind: RenderKind) -> Option<String> {
+        let key = Self::key_of(path, kind)?;
+
+        if let Ok(mut entries) = self.entries.lock() {
+            if let Some(html) = entries.get(&key) {
+                return Some(html.clone());
+            }
+        }
+
+        if !self.persist {
+            return None;
+        }
+
+        let html = RenderCacheOrm::default()
+            .find_html(db, kind.as_str(), &path.to_string_lossy(), key.mtime)
+            .await
+            .map_err(|e| error!("find_html() failed for {path:?}: {e:?}"))
+            .ok()??;
+
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.put(key, html.clone());
+        }
+
+        Some(html)
+    }
+
+    pub async fn insert(&self, db: &Db, path: &Path, kind: RenderKind, html: &str) {
+        let key = match Self::key_of(path, kind) {
+            Some(key) => key,
+            None => return,
+        };
+
+        if self.persist {
+            if let Err(e) = RenderCacheOrm::default()
+                .upsert_html(db, kind.as_str(), &path.to_string_lossy(), key.mtime, html)
+                .await
+            {
+                error!("upsert_html() failed for {path:?}: {e:?}");
+            }
+        }
+
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.put(key, html.to_owned());
+        }
+    }
+}
+
+pub struct RenderStamp {
+    pub etag: String,
+    pub last_modified: SystemTime,
+}
+
+impl RenderStamp {
+    // * Pages also show wiki links & backlinks of other notes, any index change makes them stale
+    pub fn of(path: &Path, viewer: &str, index_version: IndexVersion) -> Option<Self> {
+        let metadata = path.metadata().ok()?;
+        let file_modified = metadata.modified().ok()?;
+        let dir_modified = path.parent().and_then(modified_of);
+
+        let mut hasher = DefaultHasher::new();
+        path.hash(&mut hasher);
+        metadata.len().hash(&mut hasher);
+        nanos_of(file_modified).hash(&mut hasher);
+        dir_modified.map(nanos_of).hash(&mut hasher);
+        viewer.hash(&mut hasher);
+        index_version.generation.hash(&mut hasher);
+
+        Some(Self {
+            etag: format!("W/\"{:016x}\"", hasher.finish()),
+            last_modified: [dir_modified, Some(index_version.updated_at)]
+                .into_iter()
+                .flatten()
+                .fold(file_modified, SystemTime::max),
+        })
+    }
+
+    // * Strong validator of the file bytes alone, as `If-Range` requires
+    pub fn of_file(path: &Path) -> Option<Self> {
+        let metadata = path.metadata().ok()?;
+        let file_modified = metadata.modified().ok()?;
+
+        let mut hasher = DefaultHasher::new();
+        path.hash(&mut hasher);
+        metadata.len().hash(&mut hasher);
+        nanos_of(file_modified).hash(&mut hasher);
+
+        Some(Self {
+            etag: format!("\"{:016x}\"", hasher.finish()),
+            last_modified: file_modified,
+        })
+    }
+
+    pub fn matches_if_range(&self, if_range: &str) -> bool {
+        let if_range = if_range.trim();
+
+        if if_range.starts_with('"') {
+            return !self.etag.starts_with("W/") && if_range == self.etag;
+        }
+
+        if if_range.starts_with("W/") {
+            return false;
+        }
+
+        httpdate::parse_http_date(if_range)
+            .map(|date| {
+                nanos_of(self.last_modified) / 1_000_000_000 == nanos_of(date) / 1_000_000_000
+            })
+            .unwrap_or(false)
+    }
+
+    pub fn is_fresh(&self, validators: &CacheValidators) -> bool {
+        let opaque_tag = |etag: &str| etag.trim().trim_start_matches("W/").to_owned();
+
+        if let Some(if_none_match) = &validators.if_none_match {
+            return if_none_match
+                .split(',')
+                .any(|etag| etag.trim() == "*" || opaque_tag(etag) == opaque_tag(&self.etag));
+        }
+
+        match validators.if_modified_since {
+            Some(if_modified_since) => {
+                nanos_of(self.last_modified) / 1_000_000_000
+                    <= nanos_of(if_modified_since) / 1_000_000_000
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod render_cache_tests {
+    use super::{RenderStamp, UNIX_EPOCH};
+    use crate::utils::guards::headers::CacheValidators;
+    use std::time::Duration;
+
+    fn stamp() -> RenderStamp {
+        RenderStamp {
+            etag: "W/\"0123456789abcdef\"".to_owned(),
+            last_modified: UNIX_EPOCH + Duration::from_millis(1_680_000_000_500),
+        }
+    }
+
+    #[test]
+    fn fresh_by_etag() {
+        let validators = |if_none_match: &str| CacheValidators {
+            if_none_match: Some(if_none_match.to_owned()),
+            if_modified_since: None,
+        };
+
+        assert!(stamp().is_fresh(&validators("W/\"0123456789abcdef\"")));
+        assert!(stamp().is_fresh(&validators("\"other\", \"0123456789abcdef\"")));
+        assert!(stamp().is_fresh(&validators("*")));
+        assert!(!stamp().is_fresh(&validators("\"other\"")));
+    }
+
+    #[test]
+    fn match_if_range() {
+        let strong_stamp = RenderStamp {
+            etag: "\"0123456789abcdef\"".to_owned(),
+            last_modified: UNIX_EPOCH + Duration::from_millis(1_680_000_000_500),
+        };
+
+        assert!(strong_stamp.matches_if_range("\"0123456789abcdef\""));
+        assert!(!strong_stamp.matches_if_range("W/\"0123456789abcdef\""));
+        assert!(!strong_stamp.matches_if_range("\"other\""));
+        assert!(strong_stamp.matches_if_range("Tue, 28 Mar 2023 10:40:00 GMT"));
+        assert!(!strong_stamp.matches_if_range("Tue, 28 Mar 2023 10:39:59 GMT"));
+        assert!(!stamp().matches_if_range("W/\"0123456789abcdef\""));
+    }
+
+    #[test]
+    fn fresh_by_last_modified() {
+        let validators = |secs: u64| CacheValidators {
+            if_none_match: None,
+            if_modified_since: Some(UNIX_EPOCH + Duration::from_secs(secs)),
+        };
+
+        assert!(stamp().is_fresh(&validators(1_680_000_000)));
+        assert!(!stamp().is_fresh(&validators(1_679_999_999)));
+        assert!(!stamp().is_fresh(&CacheValidators::default()));
+    }
+}
@@ -1,13 +1,13 @@
 use crate::shared::{ApiItem, ApiList};
 use futures::Future;
 use httpstatus::StatusCode;
-use rocket::http::ContentType;
+use rocket::http::{ContentType, Header};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
-use tempfile::NamedTempFile;
 
 use crate::shared::interfaces::{ApiError, ApiResult};
 
+use super::render_cache::RenderStamp;
 use super::template::{action_html_for_401, status_text, ErrorPage, IndexLayout, Templater};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -17,15 +17,17 @@ pub enum HbpContent {
     Json(String),
     Found(String),
     File(Box<PathBuf>),
+    FileRange(Box<PathBuf>, u64, u64),
     #[serde(skip_serializing, skip_deserializing)]
     Bytes(Vec<u8>, Box<Option<ContentType>>),
-    #[serde(skip_serializing, skip_deserializing)]
-    NamedTempFile(Box<NamedTempFile>),
+    // * No body and no `Content-Type`, as a 304 requires
+    Empty,
 }
 
 pub struct HbpResponse {
     pub status_code: StatusCode,
     pub content: HbpContent,
+    pub headers: Vec<Header<'static>>,
 }
 
 impl HbpResponse {
@@ -33,6 +35,7 @@ impl HbpResponse {
         HbpResponse {
             status_code,
             content: HbpContent::Html(html),
+            headers: vec![],
         }
     }
 
@@ -43,6 +46,7 @@ impl HbpResponse {
                 Some(content) => content,
                 None => HbpContent::Plain(String::new()),
             },
+            headers: vec![],
         }
     }
 
@@ -50,6 +54,7 @@ impl HbpResponse {
         Self {
             content: HbpContent::Plain(status_code.reason_phrase().to_owned()),
             status_code,
+            headers: vec![],
         }
     }
 
@@ -91,6 +96,7 @@ impl HbpResponse {
         Ok(HbpResponse {
             status_code,
             content: HbpContent::Json(json),
+            headers: vec![],
         })
     }
 
@@ -110,6 +116,7 @@ impl HbpResponse {
         HbpResponse {
             status_code: StatusCode::Found,
             content: HbpContent::Found(uri.into_normalized().to_string()),
+            headers: vec![],
         }
     }
 
@@ -117,8 +124,41 @@ impl HbpResponse {
         HbpResponse::ok(Some(HbpContent::File(Box::new(path))))
     }
 
-    pub fn temp_file(temp_file: NamedTempFile) -> HbpResponse {
-        HbpResponse::ok(Some(HbpContent::NamedTempFile(Box::new(temp_file))))
+    pub fn file_range(path: PathBuf, start: u64, end: u64) -> HbpResponse {
+        HbpResponse {
+            status_code: StatusCode::PartialContent,
+            content: HbpContent::FileRange(Box::new(path), start, end),
+            headers: vec![],
+        }
+    }
+
+    pub fn not_modified(stamp: &RenderStamp) -> HbpResponse {
+        HbpResponse {
+            status_code: StatusCode::NotModified,
+            content: HbpContent::Empty,
+            headers: vec![],
+        }
+        .stamped(Some(stamp))
+    }
+
+    pub fn header(mut self, name: &'static str, value: String) -> HbpResponse {
+        self.headers.push(Header::new(name, value));
+
+        self
+    }
+
+    pub fn stamped(self, stamp: Option<&RenderStamp>) -> HbpResponse {
+        let stamp = match stamp {
+            Some(stamp) => stamp,
+            None => return self,
+        };
+
+        self.header("ETag", stamp.etag.clone())
+            .header(
+                "Last-Modified",
+                httpdate::fmt_http_date(stamp.last_modified),
+            )
+            .header("Cache-Control", "no-cache".to_owned())
     }
 }
 
@@ -150,10 +190,44 @@ mod hbp_response_impls {
         fs::NamedFile,
         http::{ContentType, Header, Status},
         response::Responder,
+        tokio::{
+            self,
+            io::{AsyncRead, AsyncReadExt, AsyncSeek, ReadBuf, Take},
+        },
         Response,
     };
     use serde::Serialize;
-    use std::{error::Error, io::Cursor};
+    use std::error::Error;
+    use std::fs::File;
+    use std::io::{Cursor, Seek, SeekFrom};
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    // * A preset size keeps `Content-Length` (also for HEAD), so the body never needs seeking
+    struct FileSlice(Take<tokio::fs::File>);
+
+    impl AsyncRead for FileSlice {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.get_mut().0).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncSeek for FileSlice {
+        fn start_seek(self: Pin<&mut Self>, _: SeekFrom) -> std::io::Result<()> {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "FileSlice is NOT seekable",
+            ))
+        }
+
+        fn poll_complete(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+            Poll::Ready(Ok(0))
+        }
+    }
 
     impl<'r> Responder<'r, 'r> for HbpResponse {
         // ! FIXME: Change `respond_to` into async when async Traits roll out...!
@@ -163,6 +237,10 @@ mod hbp_response_impls {
             let status = status_from(self.status_code);
             builder.status(status);
 
+            for header in self.headers.iter().cloned() {
+                builder.header(header);
+            }
+
             match self.content {
                 HbpContent::Plain(text) => {
                     builder
@@ -186,18 +264,56 @@ mod hbp_response_impls {
                         .header(Header::new("Location", path));
                 }
                 HbpContent::File(file_path) => {
-                    return futures::executor::block_on(NamedFile::open(&*file_path))
-                        .respond_to(request)
+                    let mut response = futures::executor::block_on(NamedFile::open(&*file_path))
+                        .respond_to(request)?;
+
+                    for header in self.headers {
+                        response.set_header(header);
+                    }
+
+                    return Ok(response);
                 }
-                HbpContent::NamedTempFile(tempfile) => {
-                    return futures::executor::block_on(NamedFile::open(tempfile.into_temp_path()))
-                        .respond_to(request)
+                HbpContent::FileRange(file_path, start, end) => {
+                    let open_at_start = || -> std::io::Result<(File, u64)> {
+                        let mut file = File::open(&*file_path)?;
+                        let len = file.metadata()?.len();
+                        file.seek(SeekFrom::Start(start))?;
+
+                        Ok((file, len))
+                    };
+                    let (file, len) = open_at_start().map_err(|e| {
+                        error!("open_at_start() failed for {file_path:?}: {e}");
+                        Status::NotFound
+                    })?;
+                    let content_type = file_path
+                        .extension()
+                        .and_then(|extension| {
+                            ContentType::from_extension(&extension.to_string_lossy())
+                        })
+                        .unwrap_or(ContentType::Binary);
+
+                    let slice_len = end - start + 1;
+
+                    builder
+                        .header(content_type)
+                        .header(Header::new(
+                            "Content-Range",
+                            format!("bytes {start}-{end}/{len}"),
+                        ))
+                        .sized_body(
+                            slice_len as usize,
+                            FileSlice(tokio::fs::File::from_std(file).take(slice_len)),
+                        );
                 }
                 HbpContent::Bytes(body, content_type) => {
                     builder
                         .header(content_type.unwrap_or(ContentType::Binary))
                         .sized_body(body.len(), Cursor::new(body));
                 }
+                HbpContent::Empty => {
+                    // ? An unsized body keeps Rocket from sending `Content-Length: 0`
+                    builder.streamed_body(tokio::io::empty());
+                }
             }
 
             Ok(builder.finalize())
@@ -209,6 +325,7 @@ mod hbp_response_impls {
             let res = HbpResponse {
                 status_code: self.api_error.status_code.clone(),
                 content: HbpContent::Json(json_stringify(&self.api_error)),
+                headers: vec![],
             };
 
             res.respond_to(request)
@@ -229,6 +346,7 @@ mod hbp_response_impls {
             let res = HbpResponse {
                 status_code,
                 content: HbpContent::Json(content),
+                headers: vec![],
             };
 
             res.respond_to(request)
@@ -251,6 +369,7 @@ mod hbp_response_impls {
             HbpResponse {
                 status_code: e.status_code.clone(),
                 content: HbpContent::Json(json_stringify(&e)),
+                headers: vec![],
             }
         }
     }
@@ -343,6 +462,30 @@ mod hbp_response_impls {
         }
     }
 
+    impl From<rocket::tokio::task::JoinError> for HbpError {
+        fn from(e: rocket::tokio::task::JoinError) -> Self {
+            error!("JoinError: {e}");
+
+            ApiError::internal_server_error().into()
+        }
+    }
+
+    impl From<zip::result::ZipError> for HbpError {
+        fn from(e: zip::result::ZipError) -> Self {
+            error!("[ZipError]: {e}");
+
+            ApiError::from_message(&e.to_string(), StatusCode::InternalServerError).into()
+        }
+    }
+
+    impl From<serde_json::Error> for HbpError {
+        fn from(e: serde_json::Error) -> Self {
+            error!("[serde_json::Error]: {e}");
+
+            ApiError::from_message(&e.to_string(), StatusCode::InternalServerError).into()
+        }
+    }
+
     impl<T: Serialize> From<ApiItem<T>> for HbpJson<T> {
         fn from(item: ApiItem<T>) -> Self {
             HbpJson::<T>::Item(item)
@@ -0,0 +1,119 @@
+use ammonia::Builder;
+use std::borrow::Cow;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use super::constants::TRUSTED_MARKDOWN_ROOTS;
+
+const GENERIC_ATTRIBUTES: [&str; 3] = ["class", "id", "aria-hidden"];
+
+fn sanitizer() -> &'static Builder<'static> {
+    static SANITIZER: OnceLock<Builder<'static>> = OnceLock::new();
+
+    SANITIZER.get_or_init(|| {
+        let mut builder = Builder::default();
+
+        builder
+            .add_tags(["input"])
+            .add_generic_attributes(GENERIC_ATTRIBUTES)
+            .add_tag_attributes("a", ["data-wiki-target"])
+            .add_tag_attributes("input", ["type", "checked", "disabled"])
+            .add_tag_attributes("th", ["style"])
+            .add_tag_attributes("td", ["style"])
+            .add_tag_attributes("ol", ["start"])
+            .add_tag_attributes("img", ["width", "height", "loading"])
+            .link_rel(None)
+            .attribute_filter(|element, attribute, value| match (element, attribute) {
+                ("input", "type") => (value == "checkbox").then_some(Cow::Borrowed(value)),
+                ("th" | "td", "style") => is_text_align(value).then_some(Cow::Borrowed(value)),
+                _ => Some(Cow::Borrowed(value)),
+            });
+
+        builder
+    })
+}
+
+fn is_text_align(style: &str) -> bool {
+    matches!(
+        style.trim().trim_end_matches(';'),
+        "text-align: left" | "text-align: center" | "text-align: right"
+    )
+}
+
+pub fn is_trusted(file_path: &Path) -> bool {
+    TRUSTED_MARKDOWN_ROOTS
+        .iter()
+        .any(|trusted_root| file_path.starts_with(trusted_root))
+}
+
+pub fn sanitize_html(html: &str) -> String {
+    sanitizer().clean(html).to_string()
+}
+
+pub fn sanitize_html_of(file_path: &Path, html: String) -> String {
+    if is_trusted(file_path) {
+        html
+    } else {
+        sanitize_html(&html)
+    }
+}
+
+#[cfg(test)]
+mod sanitize_tests {
+    use super::{is_trusted, sanitize_html};
+    use crate::utils::fso::markdown_to_html;
+    use std::path::Path;
+
+    fn sanitized(markdown: &str) -> String {
+        sanitize_html(&markdown_to_html(markdown))
+    }
+
+    #[test]
+    fn trusted_paths() {
+        assert!(is_trusted(Path::new("markdown/blogs/rust/p1.md")));
+        assert!(is_trusted(Path::new("README.md")));
+        assert!(!is_trusted(Path::new("markdown/users/someone/note.md")));
+        assert!(!is_trusted(Path::new("markdown/blogs-fake/note.md")));
+    }
+
+    #[test]
+    fn strip_script_payloads() {
+        let html = sanitized("Hi <script>alert(1)</script>\n\n<script>\nfetch('/x')\n</script>");
+
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("alert(1)"));
+        assert!(!html.contains("fetch"));
+        assert!(html.contains("<p>Hi </p>"));
+    }
+
+    #[test]
+    fn strip_event_handler_payloads() {
+        let html = sanitized(
+            "<img src=\"x.png\" onerror=\"alert(1)\">\n\n<a href=\"/a\" onclick=\"alert(2)\">a</a>\n\n<svg onload=alert(3)>",
+        );
+
+        assert!(!html.contains("onerror"));
+        assert!(!html.contains("onclick"));
+        assert!(!html.contains("onload"));
+        assert!(!html.contains("<svg"));
+        assert!(html.contains(r#"<img src="x.png">"#));
+    }
+
+    #[test]
+    fn strip_javascript_urls() {
+        let html = sanitized(
+            "[click](javascript:alert(1)) <a href=\"JaVaScRiPt:alert(2)\">x</a> ![i](javascript:alert(3))",
+        );
+
+        assert!(!html.to_lowercase().contains("javascript:"));
+        assert!(html.contains("<a>click</a>"));
+    }
+
+    #[test]
+    fn keep_rendered_markup() {
+        let markdown = "# Title\n\n[[Note]] $x$\n\n- [x] done\n\n| a |\n|:-:|\n| b |\n\n```rust\nlet x = 1;\n```\n\n```mermaid\nA-->B\n```";
+        let html = markdown_to_html(markdown);
+
+        assert_eq!(sanitize_html(&html), html.replace("/>", ">"));
+    }
+}
@@ -0,0 +1,465 @@
+use log::error;
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::data::models::search_doc::SearchDoc;
+use crate::data::search_orm::SearchOrm;
+use crate::shared::entities::markdown::FsoMarkdown;
+
+use super::fso::{is_markdown, is_plaintext, markdown_files_in};
+use super::responders::HbpResult;
+use super::wiki::{wiki_links_in, WikiIndex};
+
+pub const SEARCH_ROOTS: [&str; 2] = ["markdown/blogs", "markdown/users"];
+const MAX_SEARCH_HITS: usize = 50;
+const SNIPPET_LENGTH: usize = 160;
+const INDEX_VERSION: u32 = 2;
+
+const TITLE_WEIGHT: u32 = 5;
+const TAG_WEIGHT: u32 = 3;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SearchHit {
+    pub title: String,
+    pub url: String,
+    pub tags: Vec<String>,
+    pub snippet: String,
+    pub score: u32,
+}
+
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut word_start: Option<usize> = None;
+
+    text.char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+        .filter_map(move |(index, char)| {
+            if char.is_alphanumeric() {
+                word_start.get_or_insert(index);
+                None
+            } else {
+                word_start.take().map(|start| (start, &text[start..index]))
+            }
+        })
+}
+
+pub fn tokenize(text: &str) -> Vec<String> {
+    words(text).map(|(_, word)| word.to_lowercase()).collect()
+}
+
+fn mtime_of(path: &Path) -> i64 {
+    path.metadata()
+        .and_then(|metadata| metadata.modified())
+        .map(|modified| chrono::DateTime::<chrono::Utc>::from(modified).timestamp_millis())
+        .unwrap_or_default()
+}
+
+fn index_doc(path: &Path, mtime: i64) -> HbpResult<SearchDoc> {
+    let markdown = FsoMarkdown::from_markdown(path)?;
+    let tags = markdown.tags.clone().unwrap_or_default();
+
+    let mut terms: BTreeMap<String, u32> = BTreeMap::new();
+    let mut add_terms = |text: &str, weight: u32| {
+        for term in tokenize(text) {
+            *terms.entry(term).or_default() += weight;
+        }
+    };
+
+    add_terms(&markdown.title, TITLE_WEIGHT);
+    add_terms(&tags.join(" "), TAG_WEIGHT);
+    add_terms(markdown.body(), 1);
+
+    let links = wiki_links_in(markdown.body())
+        .into_iter()
+        .map(|wiki_link| wiki_link.target)
+        .collect();
+
+    Ok(SearchDoc {
+        path: path.to_string_lossy().into_owned(),
+        url: markdown.url,
+        title: markdown.title,
+        tags,
+        mtime,
+        draft: markdown.draft,
+        published_at: markdown
+            .published_at
+            .map(|published_at| published_at.timestamp_millis()),
+        terms,
+        links,
+        version: INDEX_VERSION,
+    })
+}
+
+fn is_searchable(path: &Path) -> bool {
+    let is_hidden = path
+        .iter()
+        .any(|component| component.to_string_lossy().starts_with('.'));
+
+    SEARCH_ROOTS.iter().any(|root| path.starts_with(root))
+        && (is_markdown(path) || is_plaintext(path))
+        && !is_hidden
+}
+
+// * Files API paths are absolute, the search roots are relative to the working directory
+fn search_path_of(cwd: &Path, path: &Path) -> Option<PathBuf> {
+    let path = match path.is_absolute() {
+        true => path.strip_prefix(cwd).ok()?.to_owned(),
+        false => path.to_owned(),
+    };
+
+    SEARCH_ROOTS
+        .iter()
+        .any(|root| path.starts_with(root) || Path::new(root).starts_with(&path))
+        .then_some(path)
+}
+
+// * Returns whether any doc was added, changed or removed
+async fn refresh_docs(db: &Db) -> HbpResult<bool> {
+    let search_orm = SearchOrm::default();
+    let mut indexed_paths = HashSet::new();
+    let mut changed = false;
+
+    for file_path in SEARCH_ROOTS
+        .iter()
+        .flat_map(|root| markdown_files_in(Path::new(root)))
+    {
+        let path = file_path.to_string_lossy().into_owned();
+        let mtime = mtime_of(&file_path);
+
+        let is_fresh = search_orm
+            .find_doc(db, &path)
+            .await?
+            .map(|doc| doc.mtime == mtime && doc.version == INDEX_VERSION)
+            .unwrap_or(false);
+
+        if !is_fresh {
+            match index_doc(&file_path, mtime) {
+                Ok(doc) => {
+                    search_orm.upsert_doc(db, doc).await?;
+                    changed = true;
+                }
+                Err(e) => error!("index_doc() failed for {path}: {e:?}"),
+            }
+        }
+
+        indexed_paths.insert(path);
+    }
+
+    for path in search_orm.doc_paths(db).await? {
+        if !indexed_paths.contains(&path) {
+            search_orm.remove_doc(db, &path).await?;
+            changed = true;
+        }
+    }
+
+    Ok(changed)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct IndexVersion {
+    pub generation: u64,
+    pub updated_at: SystemTime,
+}
+
+// * Pages also depend on other notes (wiki links, backlinks), through the docs of this index
+pub struct SearchIndex {
+    version: RwLock<IndexVersion>,
+    wiki_indexes: RwLock<HashMap<PathBuf, Arc<WikiIndex>>>,
+}
+
+impl Default for SearchIndex {
+    fn default() -> Self {
+        let now = SystemTime::now();
+        // ? Starting from the boot time keeps ETags of a previous run from matching
+        let generation = now
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or_default();
+
+        Self {
+            version: RwLock::new(IndexVersion {
+                generation,
+                updated_at: now,
+            }),
+            wiki_indexes: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl SearchIndex {
+    pub fn version(&self) -> IndexVersion {
+        self.version
+            .read()
+            .map(|version| *version)
+            .unwrap_or(IndexVersion {
+                generation: 0,
+                updated_at: UNIX_EPOCH,
+            })
+    }
+
+    fn bump(&self) {
+        if let Ok(mut version) = self.version.write() {
+            version.generation += 1;
+            version.updated_at = SystemTime::now();
+        }
+
+        if let Ok(mut wiki_indexes) = self.wiki_indexes.write() {
+            wiki_indexes.clear();
+        }
+    }
+
+    // * Walks every search root, for startup or when the written files are unknown
+    pub async fn refresh(&self, db: &Db) -> HbpResult<()> {
+        if refresh_docs(db).await? {
+            self.bump();
+        }
+
+        Ok(())
+    }
+
+    // * Re-indexes the written files only, the ones gone are dropped
+    pub async fn update(&self, db: &Db, file_paths: &[PathBuf]) -> HbpResult<()> {
+        let search_orm = SearchOrm::default();
+        let mut changed = false;
+
+        for file_path in file_paths
+            .iter()
+            .filter(|file_path| is_searchable(file_path))
+        {
+            let path = file_path.to_string_lossy().into_owned();
+
+            if file_path.is_file() {
+                match index_doc(file_path, mtime_of(file_path)) {
+                    Ok(doc) => {
+                        search_orm.upsert_doc(db, doc).await?;
+                        changed = true;
+                    }
+                    Err(e) => error!("index_doc() failed for {path}: {e:?}"),
+                }
+            } else if search_orm.find_doc(db, &path).await?.is_some() {
+                search_orm.remove_doc(db, &path).await?;
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.bump();
+        }
+
+        Ok(())
+    }
+
+    // * Written paths may be whole folders, their notes are only known by walking the roots
+    pub async fn reindex(&self, db: &Db, written_paths: &[PathBuf]) -> HbpResult<()> {
+        let cwd = std::env::current_dir()?.canonicalize()?;
+        let file_paths = written_paths
+            .iter()
+            .filter_map(|written_path| search_path_of(&cwd, written_path))
+            .collect::<Vec<_>>();
+
+        let has_folders = file_paths.iter().any(|file_path| {
+            file_path.is_dir() || !(is_markdown(file_path) || is_plaintext(file_path))
+        });
+
+        if has_folders {
+            self.refresh(db).await
+        } else {
+            self.update(db, &file_paths).await
+        }
+    }
+
+    pub async fn wiki_index_of(&self, db: &Db, root: &Path) -> HbpResult<Arc<WikiIndex>> {
+        if let Some(wiki_index) = self
+            .wiki_indexes
+            .read()
+            .ok()
+            .and_then(|wiki_indexes| wiki_indexes.get(root).cloned())
+        {
+            return Ok(wiki_index);
+        }
+
+        let generation = self.version().generation;
+        let docs = SearchOrm::default()
+            .find_docs(db, &root.to_string_lossy())
+            .await?;
+        let wiki_index = Arc::new(WikiIndex::of(root, &docs));
+
+        // ? A refresh may have landed while building, that index is already stale
+        if let Ok(mut wiki_indexes) = self.wiki_indexes.write() {
+            if self.version().generation == generation {
+                wiki_indexes.insert(root.to_owned(), wiki_index.clone());
+            }
+        }
+
+        Ok(wiki_index)
+    }
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+
+    index
+}
+
+pub fn snippet_of(body: &str, terms: &[String]) -> String {
+    let match_start = words(body)
+        .find(|(_, word)| {
+            let word = word.to_lowercase();
+            terms.iter().any(|term| word.starts_with(term))
+        })
+        .map(|(start, _)| start)
+        .unwrap_or_default();
+
+    let start = floor_char_boundary(body, match_start.saturating_sub(SNIPPET_LENGTH / 2));
+    let end = floor_char_boundary(body, (start + SNIPPET_LENGTH).min(body.len()));
+
+    let snippet = body[start..end]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    match (start > 0, end < body.len()) {
+        (true, true) => format!("…{snippet}…"),
+        (true, false) => format!("…{snippet}"),
+        (false, true) => format!("{snippet}…"),
+        (false, false) => snippet,
+    }
+}
+
+pub async fn search<F, D>(
+    db: &Db,
+    query: &str,
+    can_access: F,
+    can_see_draft: D,
+) -> HbpResult<Vec<SearchHit>>
+where
+    F: Fn(&Path) -> bool,
+    D: Fn(&Path) -> bool,
+{
+    let terms = tokenize(query);
+
+    if terms.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let search_orm = SearchOrm::default();
+    let mut matched_paths: Option<HashSet<String>> = None;
+
+    for term in &terms {
+        let paths = search_orm.find_paths(db, term).await?;
+
+        matched_paths = Some(match matched_paths {
+            Some(matched_paths) => matched_paths.intersection(&paths).cloned().collect(),
+            None => paths,
+        });
+    }
+
+    let mut hits = vec![];
+
+    for path in matched_paths.unwrap_or_default() {
+        if !can_access(Path::new(&path)) {
+            continue;
+        }
+
+        if let Some(doc) = search_orm.find_doc(db, &path).await? {
+            if doc.draft && !can_see_draft(Path::new(&path)) {
+                continue;
+            }
+
+            let score: u32 = doc
+                .terms
+                .iter()
+                .filter(|(doc_term, _)| terms.iter().any(|term| doc_term.starts_with(term)))
+                .map(|(_, count)| count)
+                .sum();
+
+            hits.push((doc, score));
+        }
+    }
+
+    hits.sort_by(|(doc_a, score_a), (doc_b, score_b)| {
+        score_b.cmp(score_a).then(doc_a.title.cmp(&doc_b.title))
+    });
+    hits.truncate(MAX_SEARCH_HITS);
+
+    Ok(hits
+        .into_iter()
+        .map(|(doc, score)| {
+            let snippet = FsoMarkdown::from_markdown(Path::new(&doc.path))
+                .map(|markdown| snippet_of(markdown.body(), &terms))
+                .unwrap_or_default();
+
+            SearchHit {
+                title: doc.title,
+                url: doc.url,
+                tags: doc.tags,
+                snippet,
+                score,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod search_tests {
+    use super::{is_searchable, search_path_of, snippet_of, tokenize};
+    use std::path::Path;
+
+    #[test]
+    fn tokenize_lowercases_words() {
+        assert_eq!(
+            tokenize("Hello, Rust-lang! Xin chào 2023"),
+            vec!["hello", "rust", "lang", "xin", "chào", "2023"]
+        );
+    }
+
+    #[test]
+    fn snippet_around_first_match() {
+        let body = format!("{} needle {}", "hay ".repeat(100), "stack ".repeat(100));
+        let snippet = snippet_of(&body, &["needle".to_owned()]);
+
+        assert!(snippet.starts_with('…'));
+        assert!(snippet.ends_with('…'));
+        assert!(snippet.contains("needle"));
+    }
+
+    #[test]
+    fn snippet_of_short_body() {
+        assert_eq!(
+            snippet_of("just a\nnote", &["note".to_owned()]),
+            "just a note"
+        );
+    }
+
+    #[test]
+    fn searchable_paths() {
+        assert!(is_searchable(Path::new("markdown/blogs/rust/p1.md")));
+        assert!(is_searchable(Path::new("markdown/users/hbp/notes.txt")));
+        assert!(!is_searchable(Path::new("markdown/users/hbp/.git/HEAD.md")));
+        assert!(!is_searchable(Path::new("markdown/users/hbp/cover.png")));
+        assert!(!is_searchable(Path::new("markdown/AboutMe.md")));
+    }
+
+    #[test]
+    fn search_paths_of_written_files() {
+        let cwd = Path::new("/srv/hbp");
+        let search_path = |path: &str| search_path_of(cwd, Path::new(path));
+
+        assert_eq!(
+            search_path("/srv/hbp/markdown/users/hbp/notes.md"),
+            Some("markdown/users/hbp/notes.md".into())
+        );
+        assert_eq!(search_path("/srv/hbp/markdown"), Some("markdown".into()));
+        assert_eq!(
+            search_path("markdown/blogs/rust/p1.md"),
+            Some("markdown/blogs/rust/p1.md".into())
+        );
+        assert_eq!(search_path("/srv/hbp/markdown/AboutMe.md"), None);
+        assert_eq!(search_path("/tmp/hbp/files/markdown/blogs/p1.md"), None);
+    }
+}
@@ -0,0 +1,103 @@
+use httpstatus::StatusCode;
+use log::error;
+use std::sync::OnceLock;
+use syntect::highlighting::ThemeSet;
+use syntect::html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+use crate::shared::interfaces::ApiError;
+
+use super::responders::HbpResult;
+
+const HIGHLIGHT_THEME: &str = "InspiredGitHub";
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
+
+    SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+pub fn lang_of(info_string: &str) -> &str {
+    info_string
+        .split(|char: char| char.is_whitespace() || char == ',' || char == '{')
+        .next()
+        .unwrap_or_default()
+}
+
+pub fn highlight_code(code: &str, lang: &str) -> Option<String> {
+    if lang.is_empty() {
+        return None;
+    }
+
+    let syntax_set = syntax_set();
+    let syntax = syntax_set.find_syntax_by_token(lang)?;
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, syntax_set, CLASS_STYLE);
+
+    for line in LinesWithEndings::from(code) {
+        if let Err(e) = generator.parse_html_for_line_which_includes_newline(line) {
+            error!("highlight_code() failed for {lang}: {e:?}");
+            return None;
+        }
+    }
+
+    Some(format!(
+        r#"<pre class="hl-code"><code class="language-{lang}">{}</code></pre>"#,
+        generator.finalize()
+    ))
+}
+
+pub fn highlight_css() -> HbpResult<String> {
+    let theme_set = ThemeSet::load_defaults();
+    let theme = theme_set.themes.get(HIGHLIGHT_THEME).ok_or_else(|| {
+        ApiError::from_message(
+            &format!("highlight theme {HIGHLIGHT_THEME} NOT found"),
+            StatusCode::InternalServerError,
+        )
+    })?;
+
+    css_for_theme_with_class_style(theme, CLASS_STYLE)
+        .map_err(|e| ApiError::from_message(&e.to_string(), StatusCode::InternalServerError).into())
+}
+
+#[cfg(test)]
+mod syntax_tests {
+    use super::{highlight_code, lang_of};
+    use crate::utils::fso::markdown_to_html;
+
+    #[test]
+    fn lang_from_info_string() {
+        assert_eq!(lang_of("rust"), "rust");
+        assert_eq!(lang_of("rust,ignore"), "rust");
+        assert_eq!(lang_of("js {linenos}"), "js");
+        assert_eq!(lang_of(""), "");
+    }
+
+    #[test]
+    fn highlight_known_languages() {
+        let html = highlight_code("fn main() {}\n", "rust").unwrap();
+
+        assert!(html.starts_with(r#"<pre class="hl-code"><code class="language-rust">"#));
+        assert!(html.contains(r#"<span class="hl-"#));
+    }
+
+    #[test]
+    fn unknown_languages_fall_back_to_plain_pre() {
+        assert!(highlight_code("whatever", "no-such-lang").is_none());
+
+        let html = markdown_to_html("```no-such-lang\n<b>x</b>\n```\n");
+        assert_eq!(
+            html,
+            "<pre><code class=\"language-no-such-lang\">&lt;b&gt;x&lt;/b&gt;\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn highlight_fenced_code_in_markdown() {
+        let html = markdown_to_html("# Code\n\n```rust\nlet x = 1;\n```\n\nAfter");
+
+        assert!(html.contains(r#"<pre class="hl-code"><code class="language-rust">"#));
+        assert!(html.contains("<p>After</p>"));
+    }
+}
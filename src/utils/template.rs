@@ -2,13 +2,18 @@ use crate::shared::entities::markdown::FsoMarkdown;
 use httpstatus::StatusCode;
 use mustache::Template;
 use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::vec;
 
 use super::auth::AuthPayload;
-use super::fso::markdown_to_html;
+use super::fso::{markdown_toc, Series};
+use super::math::{is_vendored_in, KATEX_ASSETS, MERMAID_ASSETS, STATIC_ROOT};
 use super::responders::HbpResult;
+use super::toc::Toc;
 use super::url_encode_path;
+use super::wiki::Backlinks;
 
 fn compile_template(path: &PathBuf) -> HbpResult<Template> {
     mustache::compile_path(Path::new("template").join(path)).map_err(|e| e.into())
@@ -28,10 +33,6 @@ impl Templater {
         Templater::new("static/error.html".into())
     }
 
-    pub fn index() -> Self {
-        Templater::new("index.html".into())
-    }
-
     pub fn to_html(&self, data: impl Serialize) -> HbpResult<String> {
         let template = compile_template(&self.template_path)?;
 
@@ -167,12 +168,6 @@ impl IndexLayout {
         self
     }
 
-    pub fn raw_content(mut self, raw_content: &str) -> Self {
-        self.raw_content = raw_content.to_string();
-
-        self
-    }
-
     pub fn set_auth(mut self, jwt: Option<AuthPayload>) -> Self {
         let username = if let Some(jwt) = jwt {
             match jwt {
@@ -207,16 +202,63 @@ pub struct MarkdownTemplate {
     markdown_url: String,
     signed_url: String,
     markdown_title: String,
+    word_count: usize,
+    reading_minutes: usize,
+    modified: String,
+    extra: BTreeMap<String, Value>,
+    series: Option<Series>,
+    toc: Option<Toc>,
+    backlinks: Option<Backlinks>,
+    has_math: bool,
+    has_mermaid: bool,
 }
 
 impl MarkdownTemplate {
-    pub fn of(markdown: &FsoMarkdown, signed_url: Option<String>) -> MarkdownTemplate {
+    pub fn of(
+        markdown: &FsoMarkdown,
+        markdown_html: String,
+        signed_url: Option<String>,
+    ) -> MarkdownTemplate {
         MarkdownTemplate {
-            markdown_html: markdown_to_html(&markdown.content),
+            has_math: false,
+            has_mermaid: false,
+            markdown_html,
             markdown_url: markdown.url.clone(),
             markdown_title: markdown.title.clone(),
+            word_count: markdown.word_count,
+            reading_minutes: markdown.reading_minutes,
+            modified: markdown.modified.clone(),
             signed_url: signed_url.unwrap_or_default(),
+            extra: markdown.extra.clone(),
+            series: None,
+            toc: markdown
+                .toc
+                .then(|| markdown_toc(markdown.body()))
+                .flatten(),
+            backlinks: None,
         }
+        .vendored_in(Path::new(STATIC_ROOT))
+    }
+
+    fn vendored_in(mut self, static_root: &Path) -> Self {
+        self.has_math = self.markdown_html.contains(r#"class="math "#)
+            && is_vendored_in(static_root, &KATEX_ASSETS);
+        self.has_mermaid = self.markdown_html.contains(r#"<pre class="mermaid">"#)
+            && is_vendored_in(static_root, &MERMAID_ASSETS);
+
+        self
+    }
+
+    pub fn series(mut self, series: Option<Series>) -> Self {
+        self.series = series;
+
+        self
+    }
+
+    pub fn backlinks(mut self, backlinks: Option<Backlinks>) -> Self {
+        self.backlinks = backlinks;
+
+        self
     }
 }
 
@@ -269,3 +311,45 @@ pub fn action_html_for_401(redirect_url: Option<String>) -> String {
 pub fn status_text(status_code: &StatusCode) -> String {
     format!("{} | {}", status_code.as_u16(), status_code.reason_phrase())
 }
+
+#[cfg(test)]
+mod template_tests {
+    use super::{MarkdownTemplate, Templater};
+    use crate::shared::entities::markdown::FsoMarkdown;
+    use crate::utils::fso::markdown_to_html;
+    use crate::utils::math::{KATEX_ASSETS, MERMAID_ASSETS};
+    use std::fs;
+
+    #[test]
+    fn include_vendored_assets_on_math_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("math.md");
+        fs::write(&path, "# Energy\n\n$E = mc^2$").unwrap();
+
+        let markdown = FsoMarkdown::from_markdown(&path).unwrap();
+        let page_of = |static_root: &std::path::Path| {
+            let template = MarkdownTemplate::of(&markdown, markdown_to_html(markdown.body()), None)
+                .vendored_in(static_root);
+
+            Templater::new("markdown/markdown.html".into())
+                .to_html(template)
+                .unwrap()
+        };
+
+        let static_root = tempfile::tempdir().unwrap();
+        assert!(!page_of(static_root.path()).contains("katex.min.js"));
+
+        for asset in KATEX_ASSETS.iter().chain(&MERMAID_ASSETS) {
+            let asset_path = static_root.path().join(asset);
+            fs::create_dir_all(asset_path.parent().unwrap()).unwrap();
+            fs::write(asset_path, "").unwrap();
+        }
+
+        let page = page_of(static_root.path());
+        for asset in KATEX_ASSETS {
+            assert!(page.contains(&format!("/static/{asset}")), "{asset}");
+        }
+        // ? No diagram on the page, mermaid stays out even when vendored
+        assert!(!page.contains(MERMAID_ASSETS[0]));
+    }
+}
@@ -0,0 +1,433 @@
+use image::{DynamicImage, ImageFormat, ImageOutputFormat};
+use log::error;
+use rocket::form::FromFormField;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use super::constants::THUMBNAIL_CACHE_DIR;
+use super::images::THUMBNAIL_WIDTHS;
+use super::responders::HbpResult;
+use crate::shared::interfaces::ApiError;
+
+const JPEG_QUALITY: u8 = 85;
+const EXIF_ORIENTATION_TAG: u16 = 0x0112;
+// * EXIF lives in APP1, right after SOI, so the head of the file is enough
+const EXIF_SCAN_LIMIT: u64 = 128 * 1024;
+pub const STAGING_PREFIX: &str = ".thumbnail-";
+const FAILED_MARKER: &str = "failed";
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+#[derive(FromFormField, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThumbnailSize {
+    Small,
+    Medium,
+    Large,
+    Xlarge,
+}
+
+impl ThumbnailSize {
+    pub const ALL: [ThumbnailSize; 4] = [
+        ThumbnailSize::Small,
+        ThumbnailSize::Medium,
+        ThumbnailSize::Large,
+        ThumbnailSize::Xlarge,
+    ];
+
+    pub fn width(self) -> u32 {
+        match self {
+            ThumbnailSize::Small => THUMBNAIL_WIDTHS[0],
+            ThumbnailSize::Medium => THUMBNAIL_WIDTHS[1],
+            ThumbnailSize::Large => THUMBNAIL_WIDTHS[2],
+            ThumbnailSize::Xlarge => THUMBNAIL_WIDTHS[3],
+        }
+    }
+}
+
+#[derive(FromFormField, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThumbnailFormat {
+    Jpeg,
+    Png,
+    Webp,
+}
+
+impl ThumbnailFormat {
+    pub fn of_source(path: &Path) -> ThumbnailFormat {
+        match ImageFormat::from_path(path) {
+            Ok(ImageFormat::Jpeg) => ThumbnailFormat::Jpeg,
+            Ok(_) => ThumbnailFormat::Png,
+            // * Frames of videos & documents, photo-like more often than not
+            Err(_) => ThumbnailFormat::Jpeg,
+        }
+    }
+
+    // ? `image` only encodes WebP with the `webp` feature (libwebp)
+    pub fn assert_encodable(self) -> HbpResult<()> {
+        if self == ThumbnailFormat::Webp && cfg!(not(feature = "webp")) {
+            return Err(ApiError::unprocessable_entity()
+                .append_error("webp thumbnails need the `webp` feature".to_owned())
+                .into());
+        }
+
+        Ok(())
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ThumbnailFormat::Jpeg => "jpg",
+            ThumbnailFormat::Png => "png",
+            ThumbnailFormat::Webp => "webp",
+        }
+    }
+
+    fn write(self, image: &DynamicImage, file: &mut File) -> HbpResult<()> {
+        match self {
+            // * JPEG has no alpha channel, and the encoder rejects anything but 8-bit colors
+            ThumbnailFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+                .write_to(file, ImageOutputFormat::Jpeg(JPEG_QUALITY))?,
+            ThumbnailFormat::Png => image.write_to(file, ImageOutputFormat::Png)?,
+            #[cfg(feature = "webp")]
+            ThumbnailFormat::Webp => image.write_to(file, ImageOutputFormat::WebP)?,
+            #[cfg(not(feature = "webp"))]
+            ThumbnailFormat::Webp => self.assert_encodable()?,
+        }
+
+        Ok(())
+    }
+}
+
+pub fn thumbnail_cache_root() -> PathBuf {
+    PathBuf::from(THUMBNAIL_CACHE_DIR)
+}
+
+// * FNV-1a, the cache outlives the binary so the hash must NOT change between builds
+fn cache_dir_of(cache_root: &Path, path: &Path) -> PathBuf {
+    let hash = path
+        .to_string_lossy()
+        .bytes()
+        .fold(FNV_OFFSET_BASIS, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+        });
+
+    cache_root.join(format!("{hash:016x}"))
+}
+
+// * Any change to the source shows up in either its mtime or its size
+fn version_of(path: &Path) -> HbpResult<String> {
+    let metadata = path.metadata()?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default();
+
+    Ok(format!("{modified:x}-{:x}", metadata.len()))
+}
+
+fn prune_stale_thumbnails(cache_dir: &Path, version: &str) {
+    let entries = match fs::read_dir(cache_dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    let version_prefix = format!("{version}-");
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+
+        if file_name.starts_with(&version_prefix) || file_name.starts_with(STAGING_PREFIX) {
+            continue;
+        }
+
+        if let Err(e) = fs::remove_file(entry.path()) {
+            error!("prune_stale_thumbnails() failed for {file_name}: {e}");
+        }
+    }
+}
+
+fn tiff_orientation_of(tiff: &[u8]) -> Option<u16> {
+    let is_little_endian = match tiff.get(..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+
+    let u16_at = |at: usize| -> Option<u16> {
+        let bytes = [*tiff.get(at)?, *tiff.get(at + 1)?];
+
+        Some(if is_little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    };
+    let u32_at = |at: usize| -> Option<u32> {
+        let bytes: [u8; 4] = tiff.get(at..at + 4)?.try_into().ok()?;
+
+        Some(if is_little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    };
+
+    if u16_at(2)? != 42 {
+        return None;
+    }
+
+    // ? Orientation is an IFD0 tag, each entry is 12 bytes: tag, type, count, value
+    let ifd = u32_at(4)? as usize;
+
+    (0..u16_at(ifd)? as usize)
+        .find_map(|index| {
+            let entry = ifd + 2 + index * 12;
+
+            if u16_at(entry)? == EXIF_ORIENTATION_TAG {
+                u16_at(entry + 8)
+            } else {
+                None
+            }
+        })
+        .filter(|orientation| (1..=8).contains(orientation))
+}
+
+fn jpeg_orientation_of(jpeg: &[u8]) -> Option<u16> {
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+
+    let mut offset = 2;
+
+    while let (Some(0xFF), Some(marker)) = (jpeg.get(offset), jpeg.get(offset + 1)) {
+        // * Start of scan or end of image, no metadata past this point
+        if matches!(marker, 0xDA | 0xD9) {
+            return None;
+        }
+
+        let len = u16::from_be_bytes([*jpeg.get(offset + 2)?, *jpeg.get(offset + 3)?]) as usize;
+        let segment = jpeg.get(offset + 4..offset + 2 + len)?;
+
+        if *marker == 0xE1 {
+            if let Some(tiff) = segment.strip_prefix(b"Exif\0\0") {
+                return tiff_orientation_of(tiff);
+            }
+        }
+
+        offset += 2 + len;
+    }
+
+    None
+}
+
+fn exif_orientation_of(path: &Path) -> Option<u16> {
+    if ImageFormat::from_path(path).ok()? != ImageFormat::Jpeg {
+        return None;
+    }
+
+    let mut jpeg = vec![];
+    File::open(path)
+        .ok()?
+        .take(EXIF_SCAN_LIMIT)
+        .read_to_end(&mut jpeg)
+        .ok()?;
+
+    jpeg_orientation_of(&jpeg)
+}
+
+fn oriented(image: DynamicImage, orientation: Option<u16>) -> DynamicImage {
+    match orientation {
+        Some(2) => image.fliph(),
+        Some(3) => image.rotate180(),
+        Some(4) => image.flipv(),
+        Some(5) => image.rotate90().fliph(),
+        Some(6) => image.rotate90(),
+        Some(7) => image.rotate270().fliph(),
+        Some(8) => image.rotate270(),
+        _ => image,
+    }
+}
+
+// * Cached under `<hash of path>/<version>-<width>.<ext>`, stale versions are dropped on a miss
+pub fn cached_thumbnail_of(
+    cache_root: &Path,
+    path: &Path,
+    width: u32,
+    format: ThumbnailFormat,
+    decode: impl FnOnce(&Path, &Path) -> HbpResult<DynamicImage>,
+) -> HbpResult<PathBuf> {
+    let path = path.canonicalize()?;
+    format.assert_encodable()?;
+
+    let version = version_of(&path)?;
+    let cache_dir = cache_dir_of(cache_root, &path);
+    let cached_path = cache_dir.join(format!("{version}-{width}.{}", format.extension()));
+
+    if cached_path.is_file() {
+        return Ok(cached_path);
+    }
+
+    fs::create_dir_all(&cache_dir)?;
+    prune_stale_thumbnails(&cache_dir, &version);
+
+    let image = decode(&path, &cache_dir)?;
+    // ? never upscale, `thumbnail` keeps the aspect ratio within the bounds
+    let thumbnail = image.thumbnail(width.min(image.width()), u32::MAX);
+
+    // * Concurrent requests race on the rename, never on a half-written file
+    let mut staged = tempfile::Builder::new()
+        .prefix(STAGING_PREFIX)
+        .tempfile_in(&cache_dir)?;
+    format.write(&thumbnail, staged.as_file_mut())?;
+    staged.persist(&cached_path).map_err(|e| e.error)?;
+
+    Ok(cached_path)
+}
+
+fn failed_marker_of(cache_root: &Path, path: &Path) -> HbpResult<PathBuf> {
+    let path = path.canonicalize()?;
+    let version = version_of(&path)?;
+
+    Ok(cache_dir_of(cache_root, &path).join(format!("{version}-{FAILED_MARKER}")))
+}
+
+// * Sources no tool could decode are marked per version, a new version gets another try
+pub fn has_failed(cache_root: &Path, path: &Path) -> bool {
+    failed_marker_of(cache_root, path)
+        .map(|marker| marker.is_file())
+        .unwrap_or(false)
+}
+
+pub fn mark_failed(cache_root: &Path, path: &Path) -> HbpResult<()> {
+    let marker = failed_marker_of(cache_root, path)?;
+
+    if let Some(cache_dir) = marker.parent() {
+        fs::create_dir_all(cache_dir)?;
+        prune_stale_thumbnails(cache_dir, &version_of(&path.canonicalize()?)?);
+    }
+
+    File::create(marker)?;
+
+    Ok(())
+}
+
+fn thumbnail_in(
+    cache_root: &Path,
+    path: &Path,
+    width: u32,
+    format: ThumbnailFormat,
+) -> HbpResult<PathBuf> {
+    cached_thumbnail_of(cache_root, path, width, format, |path, _| {
+        Ok(oriented(image::open(path)?, exif_orientation_of(path)))
+    })
+}
+
+pub fn thumbnail_of(path: &Path, width: u32, format: ThumbnailFormat) -> HbpResult<PathBuf> {
+    thumbnail_in(&thumbnail_cache_root(), path, width, format)
+}
+
+#[cfg(test)]
+mod thumbnails_tests {
+    use super::{
+        cache_dir_of, has_failed, jpeg_orientation_of, mark_failed, oriented, thumbnail_in,
+        ThumbnailFormat,
+    };
+    use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+    use std::path::Path;
+
+    fn exif_jpeg_of(tiff: &[u8]) -> Vec<u8> {
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend_from_slice(tiff);
+
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00, 0xFF, 0xE1];
+        jpeg.extend_from_slice(&(app1.len() as u16 + 2).to_be_bytes());
+        jpeg.extend_from_slice(&app1);
+        jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02]);
+
+        jpeg
+    }
+
+    #[test]
+    fn read_exif_orientation() {
+        let big_endian = [
+            b'M', b'M', 0, 42, 0, 0, 0, 8, 0, 1, 0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0,
+        ];
+        let little_endian = [
+            b'I', b'I', 42, 0, 8, 0, 0, 0, 1, 0, 0x12, 0x01, 3, 0, 1, 0, 0, 0, 8, 0, 0, 0,
+        ];
+
+        assert_eq!(jpeg_orientation_of(&exif_jpeg_of(&big_endian)), Some(6));
+        assert_eq!(jpeg_orientation_of(&exif_jpeg_of(&little_endian)), Some(8));
+        assert_eq!(jpeg_orientation_of(&exif_jpeg_of(b"MM\0*")), None);
+        assert_eq!(jpeg_orientation_of(&[0xFF, 0xD8, 0xFF, 0xE1, 0xFF]), None);
+        assert_eq!(jpeg_orientation_of(b"not a jpeg"), None);
+    }
+
+    #[test]
+    fn apply_orientation() {
+        let image =
+            DynamicImage::ImageRgb8(RgbImage::from_fn(3, 2, |x, y| Rgb([x as u8, y as u8, 0])));
+
+        assert_eq!(oriented(image.clone(), Some(1)).dimensions(), (3, 2));
+        assert_eq!(oriented(image.clone(), Some(6)).dimensions(), (2, 3));
+
+        // * Transpose: the top-left pixel stays, the top-right lands bottom-left
+        let transposed = oriented(image, Some(5)).to_rgb8();
+        assert_eq!(transposed.get_pixel(0, 0), &Rgb([0, 0, 0]));
+        assert_eq!(transposed.get_pixel(0, 2), &Rgb([2, 0, 0]));
+    }
+
+    #[test]
+    fn stable_cache_dirs() {
+        assert_eq!(
+            cache_dir_of(Path::new("root"), Path::new("/files/a.png")),
+            Path::new("root/34442bafabd5d674")
+        );
+    }
+
+    #[test]
+    fn cache_thumbnails() {
+        let cache_root = tempfile::tempdir().unwrap();
+        let cache_root = cache_root.path();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.png");
+        RgbImage::new(800, 400).save(&path).unwrap();
+
+        let cached_path = thumbnail_in(cache_root, &path, 320, ThumbnailFormat::Jpeg).unwrap();
+        assert!(cached_path.starts_with(cache_root));
+        assert!(cached_path.to_string_lossy().ends_with("-320.jpg"));
+        assert_eq!(image::open(&cached_path).unwrap().dimensions(), (320, 160));
+        assert_eq!(
+            thumbnail_in(cache_root, &path, 320, ThumbnailFormat::Jpeg).unwrap(),
+            cached_path
+        );
+
+        // ? A new version of the source replaces the stale thumbnail
+        RgbImage::new(200, 100).save(&path).unwrap();
+        let updated_path = thumbnail_in(cache_root, &path, 320, ThumbnailFormat::Png).unwrap();
+        assert_ne!(updated_path, cached_path);
+        assert!(!cached_path.exists());
+        assert_eq!(image::open(&updated_path).unwrap().dimensions(), (200, 100));
+
+        #[cfg(not(feature = "webp"))]
+        assert!(thumbnail_in(cache_root, &path, 320, ThumbnailFormat::Webp).is_err());
+    }
+
+    #[test]
+    fn remember_failures_per_version() {
+        let cache_root = tempfile::tempdir().unwrap();
+        let cache_root = cache_root.path();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("broken.pdf");
+        std::fs::write(&path, "not a pdf").unwrap();
+
+        assert!(!has_failed(cache_root, &path));
+        mark_failed(cache_root, &path).unwrap();
+        assert!(has_failed(cache_root, &path));
+
+        std::fs::write(&path, "still not a pdf").unwrap();
+        assert!(!has_failed(cache_root, &path));
+    }
+}
@@ -0,0 +1,264 @@
+use pulldown_cmark::escape::escape_html;
+use pulldown_cmark::{Event, Tag};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::iter::Peekable;
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Toc {
+    pub entries: Vec<TocEntry>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TocEntry {
+    pub level: usize,
+    pub title: String,
+    pub id: String,
+    pub children: Option<Toc>,
+}
+
+#[derive(Debug)]
+struct Heading {
+    level: usize,
+    title: String,
+    id: String,
+}
+
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+
+    for char in text.trim().chars().flat_map(char::to_lowercase) {
+        if char.is_alphanumeric() || char == '_' {
+            slug.push(char);
+        } else if (char.is_whitespace() || char == '-') && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    let slug = slug.trim_matches('-');
+
+    if slug.is_empty() {
+        "section".to_owned()
+    } else {
+        slug.to_owned()
+    }
+}
+
+// * Wiki links & math are inlined as HTML, their text still belongs to the heading
+fn text_of_html(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+
+    for char in html.chars() {
+        match char {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            char if !in_tag => text.push(char),
+            _ => {}
+        }
+    }
+
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+fn headings_of(events: &[Event]) -> Vec<Heading> {
+    let mut headings = vec![];
+    let mut current: Option<(usize, Option<String>, String)> = None;
+
+    for event in events {
+        match (event, current.as_mut()) {
+            (Event::Start(Tag::Heading(level, id, _)), _) => {
+                current = Some((*level as usize, id.map(|id| id.to_owned()), String::new()));
+            }
+            (Event::Text(text) | Event::Code(text), Some((_, _, title))) => title.push_str(text),
+            (Event::Html(html), Some((_, _, title))) => title.push_str(&text_of_html(html)),
+            (Event::End(Tag::Heading(..)), Some(_)) => {
+                if let Some((level, id, title)) = current.take() {
+                    headings.push((level, id, title));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut used_ids: HashSet<String> = headings
+        .iter()
+        .filter_map(|(_, id, _)| id.clone())
+        .collect();
+
+    headings
+        .into_iter()
+        .map(|(level, id, title)| {
+            let id = id.unwrap_or_else(|| {
+                let slug = slugify(&title);
+                let id = (0..)
+                    .map(|index| match index {
+                        0 => slug.clone(),
+                        index => format!("{slug}-{index}"),
+                    })
+                    .find(|id| !used_ids.contains(id))
+                    .unwrap_or(slug);
+
+                used_ids.insert(id.clone());
+                id
+            });
+
+            Heading {
+                level,
+                title: title.trim().to_owned(),
+                id,
+            }
+        })
+        .collect()
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    let _ = escape_html(&mut escaped, text);
+
+    escaped
+}
+
+pub fn anchor_headings(events: Vec<Event>) -> Vec<Event> {
+    let mut headings = headings_of(&events).into_iter();
+
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::Heading(level, _, classes)) => {
+                let id = headings.next().map(|heading| heading.id).unwrap_or_default();
+                let escaped_id = escape(&id);
+
+                let class_attr = if classes.is_empty() {
+                    String::new()
+                } else {
+                    format!(r#" class="{}""#, escape(&classes.join(" ")))
+                };
+
+                Event::Html(
+                    format!(
+                        r##"<{level} id="{escaped_id}"{class_attr}><a class="markdown-anchor" href="#{escaped_id}" aria-hidden="true">#</a>"##
+                    )
+                    .into(),
+                )
+            }
+            Event::End(Tag::Heading(level, ..)) => Event::Html(format!("</{level}>\n").into()),
+            event => event,
+        })
+        .collect()
+}
+
+fn nest_headings(
+    headings: &mut Peekable<impl Iterator<Item = Heading>>,
+    parent_level: usize,
+) -> Vec<TocEntry> {
+    let mut entries = vec![];
+
+    while let Some(heading) = headings.next_if(|heading| heading.level > parent_level) {
+        let children = nest_headings(headings, heading.level);
+
+        entries.push(TocEntry {
+            level: heading.level,
+            title: heading.title,
+            id: heading.id,
+            children: (!children.is_empty()).then_some(Toc { entries: children }),
+        });
+    }
+
+    entries
+}
+
+pub fn toc_of(events: &[Event]) -> Option<Toc> {
+    let entries = nest_headings(&mut headings_of(events).into_iter().peekable(), 0);
+
+    (!entries.is_empty()).then_some(Toc { entries })
+}
+
+#[cfg(test)]
+mod toc_tests {
+    use super::{slugify, toc_of, Toc, TocEntry};
+    use crate::utils::fso::{markdown_to_html, markdown_toc};
+
+    #[test]
+    fn slugify_headings() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  Tiếng Việt -- 2023 "), "tiếng-việt-2023");
+        assert_eq!(slugify("snake_case `code`"), "snake_case-code");
+        assert_eq!(slugify("!!!"), "section");
+    }
+
+    #[test]
+    fn heading_ids_are_unique_and_respect_attributes() {
+        let html = markdown_to_html("# Intro\n\n## Intro\n\n## Custom {#intro-1 .big}\n\n# Intro");
+
+        assert!(html.contains(r#"<h1 id="intro">"#));
+        assert!(html.contains(r#"<h2 id="intro-2">"#));
+        assert!(html.contains(r#"<h2 id="intro-1" class="big">"#));
+        assert!(html.contains(r#"<h1 id="intro-3">"#));
+        assert!(html.contains(
+            r##"<a class="markdown-anchor" href="#intro" aria-hidden="true">#</a>Intro</h1>"##
+        ));
+    }
+
+    #[test]
+    fn nested_toc() {
+        let entry = |level, title: &str, id: &str, children: Vec<TocEntry>| TocEntry {
+            level,
+            title: title.to_owned(),
+            id: id.to_owned(),
+            children: (!children.is_empty()).then_some(Toc { entries: children }),
+        };
+
+        assert_eq!(
+            markdown_toc("## Skipped h1\n\n# A\n\n## A.1\n\n### A.1.a\n\n## A.2\n\n# B `code`"),
+            Some(Toc {
+                entries: vec![
+                    entry(2, "Skipped h1", "skipped-h1", vec![]),
+                    entry(
+                        1,
+                        "A",
+                        "a",
+                        vec![
+                            entry(2, "A.1", "a1", vec![entry(3, "A.1.a", "a1a", vec![])]),
+                            entry(2, "A.2", "a2", vec![]),
+                        ]
+                    ),
+                    entry(1, "B code", "b-code", vec![]),
+                ]
+            })
+        );
+        assert!(toc_of(&[]).is_none());
+    }
+
+    #[test]
+    fn toc_matches_anchors_of_rewritten_headings() {
+        let markdown = "# See [[Note A|the A]] & [[B]]\n\n## Energy $E = mc^2$\n\n## Plain";
+        let html = markdown_to_html(markdown);
+        let toc = markdown_toc(markdown).unwrap();
+
+        let ids = |entries: &[TocEntry]| {
+            entries
+                .iter()
+                .flat_map(|entry| {
+                    std::iter::once(entry.id.clone()).chain(
+                        entry
+                            .children
+                            .iter()
+                            .flat_map(|toc| toc.entries.iter().map(|entry| entry.id.clone())),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids(&toc.entries), ["see-the-a-b", "energy-e-mc2", "plain"]);
+        assert_eq!(toc.entries[0].title, "See the A & B");
+
+        for id in ids(&toc.entries) {
+            assert!(html.contains(&format!(r#"id="{id}""#)), "{id} in {html}");
+        }
+    }
+}
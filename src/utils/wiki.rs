@@ -0,0 +1,461 @@
+use pulldown_cmark::escape::escape_html;
+use pulldown_cmark::{CowStr, Event, Parser, Tag};
+use regex::{Captures, Regex};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+
+use crate::data::models::search_doc::SearchDoc;
+
+use super::fso::{is_markdown, markdown_options};
+use super::url_encode_path;
+
+const WIKI_LINK_PATTERN: &str = r"\[\[([^\[\]|]+)(?:\|([^\[\]]+))?\]\]";
+const WIKI_ANCHOR_PATTERN: &str = r##"<a class="wiki-link" data-wiki-target="([^"]*)" href="#">"##;
+
+#[derive(Debug, PartialEq)]
+pub struct WikiLink {
+    pub target: String,
+    pub label: String,
+}
+
+fn wiki_link_regex() -> Regex {
+    Regex::new(WIKI_LINK_PATTERN).expect("valid wiki link regex")
+}
+
+fn wiki_link_of(captures: &Captures) -> WikiLink {
+    let target = captures[1].trim().to_owned();
+    let label = captures
+        .get(2)
+        .map(|label| label.as_str().trim().to_owned())
+        .unwrap_or_else(|| target.clone());
+
+    WikiLink { target, label }
+}
+
+struct TextRun<'a> {
+    event: Event<'a>,
+    source_range: Range<usize>,
+    in_code_block: bool,
+}
+
+fn merge_texts<'a>(events: impl Iterator<Item = (Event<'a>, Range<usize>)>) -> Vec<TextRun<'a>> {
+    let mut merged: Vec<TextRun> = vec![];
+    let mut in_code_block = false;
+
+    for (event, source_range) in events {
+        match (merged.last_mut(), event) {
+            (Some(prev_run), Event::Text(text)) if matches!(prev_run.event, Event::Text(_)) => {
+                if let Event::Text(prev_text) = &mut prev_run.event {
+                    *prev_text = CowStr::from(format!("{prev_text}{text}"));
+                }
+                prev_run.source_range.end = source_range.end;
+            }
+            (_, event) => {
+                match &event {
+                    Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+                    Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+                    _ => {}
+                }
+
+                merged.push(TextRun {
+                    event,
+                    source_range,
+                    in_code_block,
+                });
+            }
+        }
+    }
+
+    merged
+}
+
+fn wiki_links_of(text: &str, source: &str) -> Vec<(Range<usize>, WikiLink)> {
+    let wiki_link_regex = wiki_link_regex();
+    let source_targets: Vec<String> = wiki_link_regex
+        .captures_iter(source)
+        .map(|captures| wiki_link_of(&captures).target)
+        .collect();
+
+    let mut wiki_links: Vec<(Range<usize>, WikiLink)> = wiki_link_regex
+        .captures_iter(text)
+        .filter_map(|captures| Some((captures.get(0)?.range(), wiki_link_of(&captures))))
+        .collect();
+
+    if source_targets.len() == wiki_links.len() {
+        for ((_, wiki_link), source_target) in wiki_links.iter_mut().zip(source_targets) {
+            wiki_link.target = source_target;
+        }
+    }
+
+    wiki_links
+}
+
+pub fn link_wiki_pages<'a>(
+    markdown: &str,
+    events: impl Iterator<Item = (Event<'a>, Range<usize>)>,
+) -> Vec<Event<'a>> {
+    let mut linked = vec![];
+
+    for run in merge_texts(events) {
+        let text = match run.event {
+            Event::Text(text) if !run.in_code_block => text,
+            event => {
+                linked.push(event);
+                continue;
+            }
+        };
+
+        let source = markdown.get(run.source_range).unwrap_or_default();
+        let mut last_end = 0;
+
+        for (range, wiki_link) in wiki_links_of(&text, source) {
+            if range.start > last_end {
+                linked.push(Event::Text(text[last_end..range.start].to_owned().into()));
+            }
+
+            let mut label = String::new();
+            let _ = escape_html(&mut label, &wiki_link.label);
+
+            linked.push(Event::Html(
+                format!(
+                    r##"<a class="wiki-link" data-wiki-target="{}" href="#">{label}</a>"##,
+                    urlencoding::encode(&wiki_link.target)
+                )
+                .into(),
+            ));
+
+            last_end = range.end;
+        }
+
+        match last_end {
+            0 => linked.push(Event::Text(text)),
+            last_end if last_end < text.len() => {
+                linked.push(Event::Text(text[last_end..].to_owned().into()))
+            }
+            _ => {}
+        }
+    }
+
+    linked
+}
+
+pub fn wiki_links_in(markdown: &str) -> Vec<WikiLink> {
+    merge_texts(Parser::new_ext(markdown, markdown_options()).into_offset_iter())
+        .into_iter()
+        .filter(|run| !run.in_code_block)
+        .flat_map(|run| match &run.event {
+            Event::Text(text) => {
+                let source = markdown.get(run.source_range.clone()).unwrap_or_default();
+
+                wiki_links_of(text, source)
+                    .into_iter()
+                    .map(|(_, wiki_link)| wiki_link)
+                    .collect()
+            }
+            _ => vec![],
+        })
+        .collect()
+}
+
+pub fn wiki_root_of(file_path: &Path) -> Option<PathBuf> {
+    let components: Vec<Component> = file_path.components().take(3).collect();
+
+    match components
+        .iter()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [markdown, users, _] if markdown == "markdown" && users == "users" => {
+            Some(components.iter().collect())
+        }
+        [markdown, blogs, ..] if markdown == "markdown" && blogs == "blogs" => {
+            Some(Path::new("markdown").join("blogs"))
+        }
+        _ => None,
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Backlink {
+    pub title: String,
+    pub url: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Backlinks {
+    pub entries: Vec<Backlink>,
+}
+
+// * Built from the search docs of a wiki root, so page views never walk the markdown tree
+pub struct WikiIndex {
+    root: PathBuf,
+    files: Vec<PathBuf>,
+    backlinks: HashMap<PathBuf, Vec<Backlink>>,
+}
+
+impl WikiIndex {
+    pub fn empty() -> Self {
+        Self {
+            root: PathBuf::new(),
+            files: vec![],
+            backlinks: HashMap::new(),
+        }
+    }
+
+    pub fn of(root: &Path, docs: &[SearchDoc]) -> Self {
+        let docs: Vec<&SearchDoc> = docs
+            .iter()
+            .filter(|doc| {
+                Path::new(&doc.path).starts_with(root) && is_markdown(Path::new(&doc.path))
+            })
+            .collect();
+
+        let mut files: Vec<PathBuf> = docs.iter().map(|doc| PathBuf::from(&doc.path)).collect();
+        files.sort_by_key(|file_path| (file_path.components().count(), file_path.clone()));
+
+        let mut wiki_index = Self {
+            root: root.to_owned(),
+            files,
+            backlinks: HashMap::new(),
+        };
+
+        let mut backlinks: HashMap<PathBuf, Vec<Backlink>> = HashMap::new();
+
+        for doc in docs.iter().filter(|doc| !doc.draft) {
+            let mut linked_paths: Vec<&PathBuf> = doc
+                .links
+                .iter()
+                .filter_map(|target| wiki_index.resolve(target.split('#').next()?))
+                .filter(|linked_path| **linked_path != Path::new(&doc.path))
+                .collect();
+            linked_paths.sort();
+            linked_paths.dedup();
+
+            for linked_path in linked_paths {
+                backlinks
+                    .entry(linked_path.clone())
+                    .or_default()
+                    .push(Backlink {
+                        title: doc.title.clone(),
+                        url: doc.url.clone(),
+                    });
+            }
+        }
+
+        for entries in backlinks.values_mut() {
+            entries.sort_by(|a, b| a.title.cmp(&b.title));
+        }
+
+        wiki_index.backlinks = backlinks;
+
+        wiki_index
+    }
+
+    pub fn backlinks_of(&self, file_path: &Path) -> Option<Backlinks> {
+        self.backlinks.get(file_path).map(|entries| Backlinks {
+            entries: entries.clone(),
+        })
+    }
+
+    pub fn resolve(&self, target: &str) -> Option<&PathBuf> {
+        let target = target.trim().trim_start_matches('/');
+
+        if target.is_empty() {
+            return None;
+        }
+
+        let target_path = Path::new(target);
+        let is_relative = target_path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+
+        if is_relative && target.contains('/') {
+            let mut candidate = self.root.join(target_path);
+            if !is_markdown(&candidate) {
+                candidate
+                    .set_file_name(format!("{}.md", target_path.file_name()?.to_string_lossy()));
+            }
+
+            if let Some(file_path) = self.files.iter().find(|file_path| **file_path == candidate) {
+                return Some(file_path);
+            }
+        }
+
+        let target_name = target_path.file_name()?.to_string_lossy().to_lowercase();
+        let target_stem = target_name.strip_suffix(".md").unwrap_or(&target_name);
+
+        self.files.iter().find(|file_path| {
+            file_path
+                .file_stem()
+                .map(|file_stem| file_stem.to_string_lossy().to_lowercase() == target_stem)
+                .unwrap_or(false)
+        })
+    }
+
+    pub fn url_of(&self, target: &str) -> Option<String> {
+        let (target, anchor) = match target.split_once('#') {
+            Some((target, anchor)) => (target, format!("#{}", urlencoding::encode(anchor))),
+            None => (target, String::new()),
+        };
+
+        self.resolve(target)
+            .map(|file_path| format!("/{}{anchor}", url_encode_path(&file_path.to_string_lossy())))
+    }
+}
+
+pub fn resolve_wiki_links(html: &str, wiki_index: &WikiIndex) -> String {
+    let wiki_anchor_regex = Regex::new(WIKI_ANCHOR_PATTERN).expect("valid wiki anchor regex");
+
+    wiki_anchor_regex
+        .replace_all(html, |captures: &Captures| {
+            let target = urlencoding::decode(&captures[1])
+                .map(|target| target.into_owned())
+                .unwrap_or_default();
+
+            match wiki_index.url_of(&target) {
+                Some(url) => format!(r#"<a class="wiki-link" href="{url}">"#),
+                None => format!(
+                    r##"<a class="wiki-link wiki-link-broken" data-wiki-target="{}" href="#" title="Note not found">"##,
+                    &captures[1]
+                ),
+            }
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod wiki_tests {
+    use super::{resolve_wiki_links, wiki_links_in, wiki_root_of, WikiIndex, WikiLink};
+    use crate::data::models::search_doc::SearchDoc;
+    use crate::utils::fso::markdown_to_html;
+    use std::path::{Path, PathBuf};
+
+    fn doc_of(path: &str, links: &[&str], draft: bool) -> SearchDoc {
+        let title = Path::new(path)
+            .file_stem()
+            .map(|file_stem| file_stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        SearchDoc {
+            path: path.to_owned(),
+            url: path.to_owned(),
+            title,
+            tags: vec![],
+            mtime: 0,
+            draft,
+            published_at: None,
+            terms: Default::default(),
+            links: links.iter().map(|link| link.to_string()).collect(),
+            version: 0,
+        }
+    }
+
+    #[test]
+    fn parse_wiki_links_outside_code() {
+        let link = |target: &str, label: &str| WikiLink {
+            target: target.to_owned(),
+            label: label.to_owned(),
+        };
+
+        assert_eq!(
+            wiki_links_in("See [[Note A]] and [[dir/note b|the B]].\n\n`[[skipped]]`\n\n```\n[[skipped]]\n```"),
+            vec![link("Note A", "Note A"), link("dir/note b", "the B")]
+        );
+    }
+
+    #[test]
+    fn render_wiki_links() {
+        let html = markdown_to_html("Go to [[Note \"A\"|label & more]] now");
+
+        assert_eq!(
+            html,
+            "<p>Go to <a class=\"wiki-link\" data-wiki-target=\"Note%20%22A%22\" href=\"#\">label &amp; more</a> now</p>\n"
+        );
+    }
+
+    #[test]
+    fn wiki_roots() {
+        assert_eq!(
+            wiki_root_of(Path::new("markdown/users/hbp/_journal/2023/note.md")),
+            Some(PathBuf::from("markdown/users/hbp"))
+        );
+        assert_eq!(
+            wiki_root_of(Path::new("markdown/blogs/rust/p1.md")),
+            Some(PathBuf::from("markdown/blogs"))
+        );
+        assert_eq!(wiki_root_of(Path::new("markdown/AboutMe.md")), None);
+        assert_eq!(wiki_root_of(Path::new("README.md")), None);
+    }
+
+    #[test]
+    fn resolve_against_wiki_index() {
+        let root = Path::new("markdown/users/hbp");
+        let wiki_index = WikiIndex::of(
+            root,
+            &[
+                doc_of("markdown/users/hbp/Note A.md", &[], false),
+                doc_of("markdown/users/hbp/dir/note b.md", &[], false),
+                doc_of("markdown/users/hbp/dir/Note A.md", &[], false),
+                doc_of("markdown/users/hbp2/other.md", &[], false),
+            ],
+        );
+
+        assert_eq!(wiki_index.resolve("note a"), Some(&root.join("Note A.md")));
+        assert_eq!(
+            wiki_index.resolve("dir/Note A"),
+            Some(&root.join("dir").join("Note A.md"))
+        );
+        assert_eq!(
+            wiki_index.resolve("note b.md"),
+            Some(&root.join("dir").join("note b.md"))
+        );
+        assert_eq!(
+            wiki_index.resolve("../Note A"),
+            Some(&root.join("Note A.md"))
+        );
+        assert_eq!(wiki_index.resolve("missing"), None);
+        assert_eq!(wiki_index.resolve("other"), None);
+
+        let html = resolve_wiki_links(
+            &markdown_to_html("[[note b#Part 2]] [[missing]]"),
+            &wiki_index,
+        );
+
+        assert!(html.contains("note%20b.md#Part%202\">note b#Part 2</a>"));
+        assert!(html.contains(r#"class="wiki-link wiki-link-broken" data-wiki-target="missing""#));
+    }
+
+    #[test]
+    fn backlinks_from_docs() {
+        let root = Path::new("markdown/blogs");
+        let wiki_index = WikiIndex::of(
+            root,
+            &[
+                doc_of("markdown/blogs/a.md", &["b", "B#intro", "a"], false),
+                doc_of("markdown/blogs/b.md", &[], false),
+                doc_of("markdown/blogs/c.md", &["b"], true),
+                doc_of("markdown/blogs/d.md", &["b"], false),
+            ],
+        );
+
+        let titles_of = |path: &str| {
+            wiki_index
+                .backlinks_of(Path::new(path))
+                .map(|backlinks| {
+                    backlinks
+                        .entries
+                        .into_iter()
+                        .map(|backlink| backlink.title)
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        };
+
+        // * Drafts do NOT link, links to itself are NOT backlinks
+        assert_eq!(titles_of("markdown/blogs/b.md"), ["a", "d"]);
+        assert!(titles_of("markdown/blogs/a.md").is_empty());
+    }
+}
@@ -0,0 +1,110 @@
+// Lightbox navigation over the gallery grid, plus a slideshow fed by /api/v1/files/random/raw.
+(() => {
+  const SLIDESHOW_INTERVAL = 5000
+
+  const gallery = document.querySelector('.hbp-gallery')
+  const lightbox = document.querySelector('.gallery-lightbox')
+
+  if (!gallery || !lightbox) return
+
+  const image = lightbox.querySelector('img')
+  const caption = lightbox.querySelector('figcaption')
+  const items = [...gallery.querySelectorAll('.gallery-item[data-lightbox]')]
+
+  let index = -1
+  let slideshow = null
+
+  const show = ({ url, name }) => {
+    image.src = url
+    image.alt = name
+    caption.textContent = name
+    lightbox.hidden = false
+  }
+
+  const showItem = (nextIndex) => {
+    if (!items.length) return
+
+    index = (nextIndex + items.length) % items.length
+    show({ url: items[index].href, name: items[index].title })
+  }
+
+  const stopSlideshow = () => {
+    clearTimeout(slideshow?.timer)
+    slideshow = null
+    lightbox.classList.remove('is-slideshow')
+  }
+
+  const close = () => {
+    stopSlideshow()
+    lightbox.hidden = true
+    image.removeAttribute('src')
+  }
+
+  const nextSlide = async () => {
+    if (!slideshow) return
+
+    if (!slideshow.queue.length) {
+      try {
+        const response = await fetch(gallery.dataset.slideshowUrl, { credentials: 'same-origin' })
+        if (!response.ok) throw new Error(`${response.status}`)
+
+        const { items } = await response.json()
+        slideshow.queue = items
+      } catch (e) {
+        console.error('nextSlide() failed', e)
+      }
+    }
+
+    const slide = slideshow?.queue.shift()
+
+    if (!slide) {
+      close()
+      return
+    }
+
+    show({ url: slide.url, name: slide.path.split('/').pop() })
+    slideshow.timer = setTimeout(nextSlide, SLIDESHOW_INTERVAL)
+  }
+
+  items.forEach((item, itemIndex) => {
+    item.addEventListener('click', (e) => {
+      e.preventDefault()
+      showItem(itemIndex)
+    })
+  })
+
+  gallery.querySelector('.gallery-slideshow')?.addEventListener('click', () => {
+    stopSlideshow()
+    slideshow = { queue: [], timer: null }
+    lightbox.classList.add('is-slideshow')
+    nextSlide()
+  })
+
+  lightbox.querySelector('.gallery-prev').addEventListener('click', () => showItem(index - 1))
+  lightbox.querySelector('.gallery-next').addEventListener('click', () => showItem(index + 1))
+  lightbox.querySelector('.gallery-close').addEventListener('click', close)
+  lightbox.addEventListener('click', (e) => {
+    if (e.target === lightbox) close()
+  })
+
+  document.addEventListener('keydown', (e) => {
+    if (lightbox.hidden) return
+
+    switch (e.key) {
+      case 'Escape':
+        close()
+        break
+      case 'ArrowLeft':
+        if (!slideshow) showItem(index - 1)
+        break
+      case 'ArrowRight':
+        if (slideshow) {
+          clearTimeout(slideshow.timer)
+          nextSlide()
+        } else {
+          showItem(index + 1)
+        }
+        break
+    }
+  })
+})()
@@ -0,0 +1,22 @@
+// Renders math and mermaid markup emitted by the markdown renderer.
+// The libraries are vendored under /static/vendor; when missing, the raw source stays visible.
+(() => {
+  if (window.katex) {
+    document.querySelectorAll('.hbp-markdown .math').forEach(el => {
+      try {
+        katex.render(el.textContent, el, {
+          displayMode: el.classList.contains('math-display'),
+          throwOnError: false,
+        })
+        el.classList.add('math-rendered')
+      } catch (e) {
+        console.error('katex.render() failed', e)
+      }
+    })
+  }
+
+  if (window.mermaid) {
+    mermaid.initialize({ startOnLoad: false, securityLevel: 'strict' })
+    mermaid.run({ querySelector: '.hbp-markdown pre.mermaid' })
+  }
+})()